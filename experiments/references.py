@@ -0,0 +1,46 @@
+# Reference populations and hashes for the benchmark cases.
+#
+# usage: python references.py PATTERN.mc GENERATIONS...
+#
+# The OTCA fields are saved from the viewer with "Save as MacroCell" right
+# after "Recreate with depth"; the hash is the one of `formats::hash_cells`.
+import sys
+import numpy as np
+import lifelib
+
+HASH_X = 0x9e3779b97f4a7c15
+HASH_Y = 0xc2b2ae3d27d4eb4f
+MASK = 2**64 - 1
+
+
+def fmix64(h):
+    h ^= h >> 33
+    h = (h * 0xff51afd7ed558ccd) & MASK
+    h ^= h >> 33
+    h = (h * 0xc4ceb9fe1a85ec53) & MASK
+    return h ^ (h >> 33)
+
+
+def powers(base, values):
+    unique, inverse = np.unique(values, return_inverse=True)
+    table = np.array([pow(base, int(v), 2**64) for v in unique], dtype=np.uint64)
+    return table[inverse]
+
+
+def cells_hash(coords):
+    if len(coords) == 0:
+        return fmix64(0)
+    x = coords[:, 0] - coords[:, 0].min()
+    y = coords[:, 1] - coords[:, 1].min()
+    with np.errstate(over='ignore'):
+        terms = powers(HASH_X, x) * powers(HASH_Y, y)
+        return fmix64(int(terms.sum(dtype=np.uint64)))
+
+
+sess = lifelib.load_rules('b3s23')
+lt = sess.lifetree(n_layers=1, memory=8000)
+with open(sys.argv[1]) as f:
+    x = lt.pattern(f.read())
+for n in map(int, sys.argv[2:]):
+    y = x[n]
+    print(n, y.population, '%016x' % cells_hash(y.coords()))
@@ -0,0 +1,29 @@
+use gol_engines::{GoLEngine, Topology};
+
+/// Advances the field by exactly `generations` generations and returns the
+/// number of generations computed and the total shift of the field in cells,
+/// which stays exact where `f64` would not.
+///
+/// The engine only steps by powers of two, so the count is split into its
+/// binary digits; steps larger than the engine allows are repeated.
+/// `proceed` gets the number of generations computed so far before every
+/// engine update and stops the advance by returning `false`.
+pub fn advance(
+    engine: &mut dyn GoLEngine,
+    generations: u64,
+    topology: Topology,
+    mut proceed: impl FnMut(u64) -> bool,
+) -> (u64, [i64; 2]) {
+    let mut offset = [0; 2];
+    let mut remaining = generations;
+    while remaining != 0 && proceed(generations - remaining) {
+        let max_steps_log2 = engine.side_length_log2() - 1;
+        let steps_log2 = remaining.ilog2().min(max_steps_log2);
+        let [dx, dy] = engine.update(steps_log2, topology);
+
+        offset[0] += dx;
+        offset[1] += dy;
+        remaining -= 1 << steps_log2;
+    }
+    (generations - remaining, offset)
+}
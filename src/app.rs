@@ -1,35 +1,97 @@
-use super::{BrightnessStrategy, Config, FieldSource, FpsLimiter};
+use super::{
+    formats, BrightnessStrategy, Config, FieldSource, FpsLimiter, History, Job, Loader, Snapshot,
+    Worker, WorkerOutput,
+};
+use conway::Measure;
 use eframe::egui::{
-    CentralPanel, Color32, ColorImage, Context, Frame, Rect, TextureHandle, TextureOptions,
+    CentralPanel, Color32, ColorImage, Context, Event, Frame, Key, Rect, TextureHandle,
+    TextureOptions,
 };
 use egui_file::FileDialog;
+use formats::PatternFormat;
 use gol_engines::{DefaultEngine, GoLEngine, Topology};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Bookkeeping of a background job, done once it succeeds.
+pub(super) enum Pending {
+    /// Counts the generations; records the field before them if labelled.
+    Advance(Option<Snapshot>),
+    /// Starts over with a new field; records the old one.
+    Load {
+        record: Snapshot,
+        generation: u64,
+    },
+    /// Moves through the history; the current field is saved for the way back.
+    Undo {
+        steps: usize,
+        current: Snapshot,
+    },
+    Redo(Snapshot),
+    /// Records the field before the edit and selects `selection` after it.
+    Edit {
+        record: Snapshot,
+        selection: Option<[[i64; 2]; 2]>,
+    },
+    /// Puts the encoded cells into the clipboard.
+    Copy,
+    /// Nothing to do once the file is written.
+    Save,
+    /// Shows the measure of the field at the generation.
+    Measure(u64),
+}
 
 pub struct App {
-    pub(super) life_engine: Box<dyn GoLEngine>, // Conway's GoL engine.
-    pub(super) is_paused: bool,              // Flag indicating whether the simulation is paused.
+    pub(super) life_engine: Option<Box<dyn GoLEngine + Send>>, // Conway's GoL engine; `None` while `worker` owns it.
+    pub(super) worker: Worker, // Thread that runs the jobs on the field.
+    pub(super) pending: Option<Pending>, // Bookkeeping of the background job in progress.
+    pub(super) update_chunk_log2: u32, // Chunk size the next background update starts with.
+    pub(super) side_length_log2: u32, // Side length of the field as of the last finished update.
+    pub(super) statistics: String, // Engine statistics as of the last finished update.
+    pub(super) measure: Measure, // Population and bounding box as of `measure_generation`.
+    pub(super) measure_generation: u64, // Generation of the last measure.
+    pub(super) measure_is_stale: bool, // Whether the field changed since the last measure.
+    pub(super) is_paused: bool, // Flag indicating whether the simulation is paused.
     pub(super) pause_after_updates: bool, // Flag indicating whether to pause after a certain number of updates.
     pub(super) updates_before_pause: u64, // Number of updates left before stopping.
     pub(super) do_one_step: bool,         // Do one step and pause.
     pub(super) simulation_steps_log2: u32, // Number of Conway's GoL updates per frame.
     pub(super) topology: Topology,        // Topology of the field.
     pub(super) generation: u64,           // Current generation number.
+    pub(super) origin: [i64; 2],          // Cell at the `(0, 0)` of the loaded file.
+    pub(super) target_generation: u64,    // Generation to advance to on request.
     pub(super) last_update_duration: f64, // Duration of the last life update in seconds.
     pub(super) viewport_size: f64,        // Size of the viewport in cells.
     pub(super) viewport_pos_x: f64, // Position (in the Conway's GoL field) of the left top corner of the viewport.
     pub(super) viewport_pos_y: f64,
     pub(super) viewport_buf: Vec<f64>,
     pub(super) texture: TextureHandle, // Texture handle of Conway's GoL field.
+    pub(super) texture_pos_x: f64, // Position (in the Conway's GoL field) of the left top corner of the texture.
+    pub(super) texture_pos_y: f64,
+    pub(super) texture_size: f64,       // Size of the texture in cells.
     pub(super) life_rect: Option<Rect>, // Part of the window displaying Conway's GoL field.
+    pub(super) is_selecting: bool, // Flag indicating whether dragging selects instead of panning.
+    pub(super) selection: Option<[[i64; 2]; 2]>, // Opposite corners of the selected rectangle in cells.
+    pub(super) paste: Option<Vec<(i64, i64)>>, // Cells being pasted, relative to their top-left corner.
+    pub(super) paste_cell: [i64; 2], // Cell under the cursor, where the paste would be placed.
+    pub(super) random_fill_density: f64, // Density of the random fill of the selection.
+    pub(super) copied: Option<String>, // Text to put into the clipboard.
     pub(super) fps_limiter: FpsLimiter, // Limits the frame rate to a certain value.
     pub(super) brightness_strategy: BrightnessStrategy, // Strategy for normalizing brightness.
     pub(super) brightness_shift: f64,
 
     pub(super) saved_file: Option<std::path::PathBuf>,
     pub(super) save_file_dialog: Option<FileDialog>,
+    pub(super) saved_rle_file: Option<std::path::PathBuf>,
+    pub(super) save_rle_file_dialog: Option<FileDialog>,
+    pub(super) save_comments: String, // Comment lines of saved files.
     pub(super) opened_file: Option<std::path::PathBuf>,
     pub(super) open_file_dialog: Option<FileDialog>,
 
+    pub(super) history: History, // Undo/redo history of the field.
+    pub(super) history_label: Option<String>, // Action to record before the next advance.
+    pub(super) error: Option<String>, // Message of the error dialog.
+
     pub(super) field_source: FieldSource,
     pub(super) field_source_otca_depth: u32,
     pub(super) max_fps: f64,
@@ -42,7 +104,15 @@ impl App {
         let life = DefaultEngine::default();
         Self {
             viewport_size: 2f64.powi(life.side_length_log2() as i32),
-            life_engine: Box::new(life),
+            side_length_log2: life.side_length_log2(),
+            life_engine: Some(Box::new(life)),
+            worker: Worker::new(),
+            pending: None,
+            update_chunk_log2: 0,
+            statistics: String::new(),
+            measure: Measure::default(),
+            measure_generation: 0,
+            measure_is_stale: true,
             is_paused: true,
             pause_after_updates: false,
             updates_before_pause: 0,
@@ -50,6 +120,8 @@ impl App {
             simulation_steps_log2: 0,
             topology: Topology::Unbounded,
             generation: 0,
+            origin: [0; 2],
+            target_generation: 0,
             last_update_duration: 0.,
             viewport_pos_x: 0.,
             viewport_pos_y: 0.,
@@ -59,17 +131,33 @@ impl App {
                 ColorImage::default(),
                 TextureOptions::default(),
             ),
+            texture_pos_x: 0.,
+            texture_pos_y: 0.,
+            texture_size: 1.,
             life_rect: None,
+            is_selecting: false,
+            selection: None,
+            paste: None,
+            paste_cell: [0; 2],
+            random_fill_density: Config::RANDOM_FILL_DENSITY,
+            copied: None,
             fps_limiter: FpsLimiter::default(),
             brightness_strategy: BrightnessStrategy::Golly,
             brightness_shift: 0.0,
 
             saved_file: None,
             save_file_dialog: None,
+            saved_rle_file: None,
+            save_rle_file_dialog: None,
+            save_comments: String::new(),
             opened_file: None,
             open_file_dialog: None,
 
-            field_source: FieldSource::FileMacroCell,
+            history: History::new(Config::HISTORY_DEPTH, Config::HISTORY_MEMORY),
+            history_label: None,
+            error: None,
+
+            field_source: FieldSource::File,
             field_source_otca_depth: Config::OTCA_DEPTH,
             max_fps: Config::MAX_FPS,
             zoom_step: Config::ZOOM_STEP,
@@ -85,12 +173,139 @@ impl App {
         self.simulation_steps_log2 = 0;
         self.topology = Topology::Unbounded;
         self.generation = 0;
+        self.target_generation = 0;
         self.last_update_duration = 0.;
-        self.viewport_size = 2f64.powi(self.life_engine.side_length_log2() as i32);
+        self.viewport_size = 2f64.powi(self.side_length_log2 as i32);
         self.viewport_pos_x = 0.;
         self.viewport_pos_y = 0.;
     }
 
+    fn set_engine(&mut self, engine: Box<dyn GoLEngine + Send>) {
+        self.side_length_log2 = engine.side_length_log2();
+        self.life_engine = Some(engine);
+    }
+
+    /// Replaces the field with the pattern from `file_path` in the background,
+    /// reporting failures in the error dialog.
+    pub fn open_file(&mut self, file_path: &Path) {
+        let data = match std::fs::read(file_path) {
+            Ok(data) => data,
+            Err(err) => {
+                self.error = Some(format!("Failed to read {}: {}", file_path.display(), err));
+                return;
+            }
+        };
+        let generation = formats::header_generation(&data).unwrap_or(0);
+        let extension = file_path
+            .extension()
+            .and_then(|x| x.to_str())
+            .map(str::to_owned);
+        let name = file_path.display().to_string();
+        self.load(
+            Box::new(move || {
+                let (engine, origin) =
+                    formats::load_pattern_with_origin(&data, extension.as_deref())
+                        .map_err(|reason| format!("Failed to parse {}: {}", name, reason))?;
+                Ok((Box::new(engine), origin))
+            }),
+            generation,
+        );
+    }
+
+    /// Saves the field in `format` to `file_path` in the background, reporting
+    /// failures in the error dialog.
+    pub fn save(&mut self, file_path: PathBuf, format: PatternFormat) {
+        let generation = self.generation;
+        let origin = self.origin;
+        let comments: Vec<String> = self.save_comments.lines().map(str::to_owned).collect();
+        let encoder = move |engine: &mut dyn GoLEngine| {
+            let data = formats::save_pattern(engine, format, generation, origin, &comments);
+            let result = data
+                .and_then(|data| std::fs::write(&file_path, data).map_err(|err| err.to_string()));
+            match result {
+                Ok(()) => Ok(vec![]),
+                Err(err) => Err(format!("Failed to write {}: {}", file_path.display(), err)),
+            }
+        };
+        self.spawn(Job::Encode(Box::new(encoder)), Pending::Save);
+    }
+
+    /// Replaces the field with the one built by `loader` in the background,
+    /// starting the count at `generation`.
+    pub fn load(&mut self, loader: Loader, generation: u64) {
+        let pending = Pending::Load {
+            record: self.snapshot("Recreate field"),
+            generation,
+        };
+        self.spawn(Job::Replace(loader), pending);
+    }
+
+    /// Current state without the field, which the worker saves.
+    pub(super) fn snapshot(&self, label: &str) -> Snapshot {
+        Snapshot {
+            label: label.to_owned(),
+            macrocell: Arc::default(),
+            generation: self.generation,
+            origin: self.origin,
+            topology: self.topology,
+            viewport_pos_x: self.viewport_pos_x,
+            viewport_pos_y: self.viewport_pos_y,
+            viewport_size: self.viewport_size,
+        }
+    }
+
+    /// Restores the state of a snapshot whose field is already loaded.
+    fn restore(&mut self, snapshot: &Snapshot) {
+        self.is_paused = true;
+        self.generation = snapshot.generation;
+        self.origin = snapshot.origin;
+        self.topology = snapshot.topology;
+        self.viewport_pos_x = snapshot.viewport_pos_x;
+        self.viewport_pos_y = snapshot.viewport_pos_y;
+        self.viewport_size = snapshot.viewport_size;
+    }
+
+    /// Records the field in the history before the next advance.
+    pub fn record_history(&mut self, action: &str) {
+        self.history_label = Some(action.to_owned());
+    }
+
+    /// Restores the field as it was `steps` recorded actions ago.
+    pub fn undo(&mut self, steps: usize) {
+        let Some(target) = self.history.undo_target(steps) else {
+            return;
+        };
+        let loader = Self::snapshot_loader(target);
+        let pending = Pending::Undo {
+            steps,
+            current: self.snapshot(""),
+        };
+        self.spawn(Job::Replace(loader), pending);
+    }
+
+    pub fn redo(&mut self) {
+        let Some(target) = self.history.redo_target() else {
+            return;
+        };
+        let loader = Self::snapshot_loader(target);
+        self.spawn(Job::Replace(loader), Pending::Redo(self.snapshot("")));
+    }
+
+    fn snapshot_loader(snapshot: &Snapshot) -> Loader {
+        let macrocell = snapshot.macrocell.clone();
+        let origin = snapshot.origin;
+        Box::new(move || Ok((Box::new(DefaultEngine::from_macrocell(&macrocell)), origin)))
+    }
+
+    /// Top-left and bottom-right cells of the selection.
+    pub fn selection_bounds(&self) -> Option<[[i64; 2]; 2]> {
+        let [a, b] = self.selection?;
+        Some([
+            [a[0].min(b[0]), a[1].min(b[1])],
+            [a[0].max(b[0]), a[1].max(b[1])],
+        ])
+    }
+
     pub fn reset_appearance(&mut self) {
         self.brightness_strategy = BrightnessStrategy::Linear;
         self.max_fps = Config::MAX_FPS;
@@ -99,38 +314,221 @@ impl App {
     }
 
     fn update_engine(&mut self) {
+        if self.pending.is_some() {
+            return;
+        }
         if self.pause_after_updates && self.updates_before_pause == 0 {
             self.is_paused = true;
             self.do_one_step = false;
         }
         if self.is_paused && !self.do_one_step {
+            if self.measure_is_stale {
+                self.measure_field();
+            }
             return;
         }
 
-        let timer = std::time::Instant::now();
-        {
-            let [dx, dy] = self
-                .life_engine
-                .update(self.simulation_steps_log2, self.topology);
-
-            self.viewport_pos_x += dx as f64;
-            self.viewport_pos_y += dy as f64;
-        }
-        // updating frame counter
-        self.last_update_duration = timer.elapsed().as_secs_f64();
-
-        self.generation += 1 << self.simulation_steps_log2;
+        self.advance(1 << self.simulation_steps_log2);
         if self.pause_after_updates {
             self.updates_before_pause -= 1;
         }
         self.do_one_step = false;
     }
 
+    /// Starts advancing the field by exactly `generations` generations on a
+    /// background thread. Does nothing while another job is running.
+    pub fn advance(&mut self, generations: u64) {
+        if self.pending.is_some() {
+            return;
+        }
+        let record = self.history_label.take().map(|label| self.snapshot(&label));
+        let job = Job::Advance {
+            generations,
+            topology: self.topology,
+            chunk_log2: self.update_chunk_log2,
+        };
+        self.spawn(job, Pending::Advance(record));
+    }
+
+    /// Starts measuring the field in the background, stopping the job in
+    /// progress first.
+    ///
+    /// Measuring goes through the whole MacroCell of the field, as there is
+    /// no per-node population in `gol_engines` to cache it in yet, so it is
+    /// done when the simulation is paused or on request rather than after
+    /// every update.
+    pub fn measure_field(&mut self) {
+        self.measure_is_stale = false;
+        self.spawn(Job::Measure, Pending::Measure(self.generation));
+    }
+
+    /// Starts `job` on the field in the background, stopping the job in
+    /// progress first.
+    pub(super) fn spawn(&mut self, job: Job, pending: Pending) {
+        self.stop_worker();
+        let engine = self.life_engine.take().expect("No engine with no job");
+        let save = match &pending {
+            Pending::Advance(record) => record.is_some(),
+            Pending::Load { .. } | Pending::Undo { .. } | Pending::Redo(_) => true,
+            Pending::Edit { .. } => true,
+            Pending::Copy | Pending::Save | Pending::Measure(_) => false,
+        };
+        self.worker.start(engine, job, save);
+        self.pending = Some(pending);
+    }
+
+    /// Cancels the job in progress and waits for the engine update it is
+    /// computing, so that the field is free to be replaced.
+    fn stop_worker(&mut self) {
+        if let Some(pending) = self.pending.take() {
+            self.worker.cancel();
+            let output = self.worker.join();
+            self.finish(output, pending);
+        }
+    }
+
+    /// Takes back the engine once the background job is finished.
+    fn poll_worker(&mut self) {
+        if self.pending.is_none() {
+            return;
+        }
+        if let Some(output) = self.worker.try_join() {
+            let pending = self.pending.take().unwrap();
+            self.finish(output, pending);
+        }
+    }
+
+    fn finish(&mut self, output: Result<WorkerOutput, String>, pending: Pending) {
+        let output = match output {
+            Ok(output) => output,
+            Err(message) => {
+                self.error = Some(format!(
+                    "Field update panicked, the field is cleared: {}",
+                    message
+                ));
+                self.set_engine(Box::new(DefaultEngine::default()));
+                self.measure = Measure::default();
+                self.measure_generation = self.generation;
+                self.measure_is_stale = false;
+                self.reset_viewport();
+                return;
+            }
+        };
+        self.set_engine(output.engine);
+        if output.generations != 0 {
+            self.measure_is_stale = true;
+        }
+        if let Err(message) = output.result {
+            self.error = Some(message);
+            return;
+        }
+        let saved = Arc::new(output.saved.unwrap_or_default());
+        let with_field = |snapshot: Snapshot| Snapshot {
+            macrocell: saved.clone(),
+            ..snapshot
+        };
+
+        match pending {
+            Pending::Advance(record) => {
+                self.shift(output.offset);
+                self.generation += output.generations;
+                self.last_update_duration = output.duration;
+                self.update_chunk_log2 = output.chunk_log2;
+                if let Some(record) = record {
+                    self.history.push(with_field(record));
+                }
+            }
+            Pending::Load { record, generation } => {
+                self.measure_is_stale = true;
+                self.selection = None;
+                self.history.push(with_field(record));
+                self.reset_viewport();
+                self.generation = generation;
+                self.origin = output.origin;
+            }
+            Pending::Undo { steps, current } => {
+                self.measure_is_stale = true;
+                self.selection = None;
+                let target = self.history.undo(steps, with_field(current));
+                self.restore(&target);
+            }
+            Pending::Redo(current) => {
+                self.measure_is_stale = true;
+                self.selection = None;
+                let target = self.history.redo(with_field(current));
+                self.restore(&target);
+            }
+            Pending::Edit { record, selection } => {
+                self.measure_is_stale = true;
+                self.selection = selection;
+                self.shift(output.offset);
+                self.history.push(with_field(record));
+                if !output.data.is_empty() {
+                    self.copied = Some(String::from_utf8_lossy(&output.data).into_owned());
+                }
+            }
+            Pending::Copy => {
+                self.copied = Some(String::from_utf8_lossy(&output.data).into_owned());
+            }
+            Pending::Save => {}
+            Pending::Measure(generation) => {
+                if let Some(measure) = output.measure {
+                    self.measure = measure;
+                    self.measure_generation = generation;
+                }
+            }
+        }
+    }
+
+    /// Follows the content of the field that moved by `offset` cells.
+    fn shift(&mut self, offset: [i64; 2]) {
+        self.origin[0] += offset[0];
+        self.origin[1] += offset[1];
+        self.viewport_pos_x += offset[0] as f64;
+        self.viewport_pos_y += offset[1] as f64;
+        for corner in self.selection.iter_mut().flatten() {
+            corner[0] += offset[0];
+            corner[1] += offset[1];
+        }
+    }
+
     fn update_viewport(&mut self, ctx: &Context, life_rect: Rect) {
+        let typing = ctx.wants_keyboard_input();
+        let mut pasted = None;
+        let mut placed = None;
         ctx.input(|input| {
+            if !typing {
+                for event in &input.events {
+                    if let Event::Paste(text) = event {
+                        pasted = Some(text.clone());
+                    }
+                }
+                if input.key_pressed(Key::Escape) {
+                    self.paste = None;
+                }
+            }
             if let Some(pos) = input.pointer.latest_pos() {
                 if life_rect.contains(pos) {
-                    if input.pointer.primary_down() {
+                    let p = (pos - life_rect.left_top()) / life_rect.size();
+                    let cell = [
+                        (self.viewport_pos_x + self.viewport_size * p.x as f64).floor() as i64,
+                        (self.viewport_pos_y + self.viewport_size * p.y as f64).floor() as i64,
+                    ];
+                    self.paste_cell = cell;
+                    let dragging = input.pointer.primary_down();
+                    if self.paste.is_some() && input.pointer.primary_pressed() {
+                        placed = Some(cell);
+                    } else if dragging && self.is_selecting && self.paste.is_none() {
+                        match &mut self.selection {
+                            Some(selection) if !input.pointer.primary_pressed() => {
+                                selection[1] = cell
+                            }
+                            _ => self.selection = Some([cell; 2]),
+                        }
+                    } else if dragging
+                        || input.pointer.secondary_down()
+                        || input.pointer.middle_down()
+                    {
                         let p = input.pointer.delta() / life_rect.size();
                         self.viewport_pos_x -= self.viewport_size * p.x as f64;
                         self.viewport_pos_y -= self.viewport_size * p.y as f64;
@@ -148,7 +546,7 @@ impl App {
                     }
 
                     if !matches!(self.topology, Topology::Unbounded) {
-                        let life_size = 2f64.powi(self.life_engine.side_length_log2() as i32);
+                        let life_size = 2f64.powi(self.side_length_log2 as i32);
                         self.viewport_size = self.viewport_size.min(life_size);
                         let lim = life_size - self.viewport_size;
                         self.viewport_pos_x = self.viewport_pos_x.min(lim).max(0.);
@@ -164,6 +562,12 @@ impl App {
             //     self.is_paused = !self.is_paused;
             // }
         });
+        if let Some(text) = pasted {
+            self.start_paste(&text);
+        }
+        if let Some(cell) = placed {
+            self.place_paste(cell);
+        }
     }
 }
 
@@ -181,6 +585,7 @@ impl eframe::App for App {
                 ctx.request_repaint();
 
                 // updating and drawing the field
+                self.poll_worker();
                 if let Some(life_rect) = self.life_rect {
                     self.update_viewport(ctx, life_rect);
                 }
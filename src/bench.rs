@@ -0,0 +1,156 @@
+//! Shared harness of the benchmark binaries.
+
+use super::formats;
+use gol_engines::{GoLEngine, Topology};
+use std::collections::{HashMap, HashSet};
+
+/// Benchmark: a field advanced by `generations` with optional reference results.
+pub struct Case {
+    pub name: String,
+    pub engine: Box<dyn GoLEngine>,
+    pub generations: u64,
+    pub topology: Topology,
+    pub population: Option<u128>, // Expected population after the advance.
+    pub hash: Option<u64>,        // Expected `conway::hash` after the advance.
+}
+
+impl Case {
+    /// Runs the case, prints its results and returns whether they match the
+    /// references; results without a reference are reported as unchecked.
+    pub fn run(mut self) -> bool {
+        let timer = std::time::Instant::now();
+        super::advance(&mut *self.engine, self.generations, self.topology, |_| true);
+        let time = timer.elapsed().as_secs_f64();
+
+        let population = super::population(&mut *self.engine);
+        let hash = super::hash(&mut *self.engine);
+        let checks = [
+            ("population", self.population.map(|x| x == population)),
+            ("hash", self.hash.map(|x| x == hash)),
+        ];
+        let ok = checks.iter().all(|(_, check)| *check != Some(false));
+        let status = if !ok {
+            "MISMATCH".to_owned()
+        } else if checks.iter().all(|(_, check)| check.is_some()) {
+            "ok".to_owned()
+        } else if checks.iter().all(|(_, check)| check.is_none()) {
+            "unchecked".to_owned()
+        } else {
+            let status = |(name, check): &(&str, Option<bool>)| {
+                format!(
+                    "{} {}",
+                    name,
+                    if check.is_some() { "ok" } else { "unchecked" }
+                )
+            };
+            checks.iter().map(status).collect::<Vec<_>>().join(", ")
+        };
+        println!(
+            "{}\tgenerations={}\tpopulation={}\thash={:016x}\ttime={:.3}\t{}",
+            self.name, self.generations, population, hash, time, status
+        );
+        if !ok {
+            println!(
+                "\texpected population={:?} hash={:x?}",
+                self.population, self.hash
+            );
+        }
+        ok
+    }
+}
+
+/// Runs all cases and exits with an error code if any of them mismatched.
+pub fn run_all(cases: impl IntoIterator<Item = Case>) {
+    let mut failed = 0;
+    for case in cases {
+        failed += !case.run() as u32;
+    }
+    if failed != 0 {
+        eprintln!("{} case(s) mismatched the reference values", failed);
+        std::process::exit(1);
+    }
+}
+
+/// Live cells of a `side`x`side` square with every cell alive with
+/// probability `density`, generated by a seeded xorshift.
+pub fn random_cells(side: i64, density: f64, seed: u64) -> Vec<(i64, i64)> {
+    random_rect(side, side, density, seed)
+}
+
+/// Like `random_cells`, for a `width`x`height` rectangle.
+pub fn random_rect(width: i64, height: i64, density: f64, seed: u64) -> Vec<(i64, i64)> {
+    let mut state = seed.max(1);
+    let mut cells = vec![];
+    for y in 0..height {
+        for x in 0..width {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let sample = (state >> 11) as f64 / (1u64 << 53) as f64;
+            if sample < density {
+                cells.push((x, y));
+            }
+        }
+    }
+    cells
+}
+
+/// RLE of `random_cells`.
+pub fn random_soup(side: i64, density: f64, seed: u64) -> Vec<u8> {
+    formats::cells_to_rle(&mut random_cells(side, density, seed))
+}
+
+/// One generation of the naive simulation; `wrap` is the side of the torus.
+pub fn naive_step(cells: &HashSet<(i64, i64)>, wrap: Option<i64>) -> HashSet<(i64, i64)> {
+    let mut neighbours = HashMap::new();
+    for &(x, y) in cells {
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                if (dx, dy) == (0, 0) {
+                    continue;
+                }
+                let (mut nx, mut ny) = (x + dx, y + dy);
+                if let Some(side) = wrap {
+                    (nx, ny) = (nx.rem_euclid(side), ny.rem_euclid(side));
+                }
+                *neighbours.entry((nx, ny)).or_insert(0) += 1;
+            }
+        }
+    }
+    neighbours
+        .into_iter()
+        .filter(|&(cell, n)| n == 3 || n == 2 && cells.contains(&cell))
+        .map(|(cell, _)| cell)
+        .collect()
+}
+
+/// Naive simulation on a dense `side`x`side` torus, for fields too crowded
+/// for `naive_step`; returns the live cells after `generations`.
+pub fn naive_torus(cells: &[(i64, i64)], side: usize, generations: u64) -> Vec<(i64, i64)> {
+    let mut grid = vec![false; side * side];
+    for &(x, y) in cells {
+        grid[y as usize % side * side + x as usize % side] = true;
+    }
+    let mut next = grid.clone();
+    for _ in 0..generations {
+        for y in 0..side {
+            let rows = [(y + side - 1) % side, y, (y + 1) % side].map(|r| r * side);
+            for x in 0..side {
+                let columns = [(x + side - 1) % side, x, (x + 1) % side];
+                let mut n = 0;
+                for row in rows {
+                    for column in columns {
+                        n += grid[row + column] as u32;
+                    }
+                }
+                let alive = grid[y * side + x];
+                next[y * side + x] = n == 3 || alive && n == 4;
+            }
+        }
+        std::mem::swap(&mut grid, &mut next);
+    }
+    (0..side * side)
+        .filter(|&i| grid[i])
+        .map(|i| ((i % side) as i64, (i / side) as i64))
+        .collect()
+}
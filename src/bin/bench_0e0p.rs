@@ -0,0 +1,23 @@
+use conway::{
+    bench::{self, Case},
+    formats,
+};
+use gol_engines::Topology;
+
+const PATTERN_PATH: &str = "res/0e0p-metaglider.mc";
+
+fn main() {
+    let data = std::fs::read(PATTERN_PATH)
+        .unwrap_or_else(|err| panic!("Failed to read {}: {}", PATTERN_PATH, err));
+    // populations computed by lifelib, see `experiments/main.py`; the hashes
+    // are still to be recorded with `experiments/references.py`
+    let cases = [(0, 93_235_805), (1 << 23, 93_236_670)].map(|(generations, population)| Case {
+        name: "0e0p_metaglider".to_owned(),
+        engine: Box::new(formats::load_pattern(&data, Some("mc")).unwrap()),
+        generations,
+        topology: Topology::Unbounded,
+        population: Some(population),
+        hash: None,
+    });
+    bench::run_all(cases);
+}
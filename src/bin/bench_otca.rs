@@ -0,0 +1,23 @@
+use conway::bench::{self, Case};
+use gol_engines::{DefaultEngine, Topology};
+
+// No references are recorded yet: the fields are far too large for the naive
+// simulation, so they have to come from lifelib, see `experiments/references.py`.
+// Until then the cases are reported as unchecked.
+fn main() {
+    let cases = [(2, 1 << 16), (2, 1 << 20), (3, 1 << 24)].map(|(depth, generations)| Case {
+        name: format!("otca_depth_{}", depth),
+        engine: Box::new(DefaultEngine::from_recursive_otca_metapixel(
+            depth,
+            conway::OTCA_TOP_PATTERN
+                .iter()
+                .map(|row| row.to_vec())
+                .collect(),
+        )),
+        generations,
+        topology: Topology::Unbounded,
+        population: None,
+        hash: None,
+    });
+    bench::run_all(cases);
+}
@@ -0,0 +1,46 @@
+use conway::bench::{self, Case};
+use conway::formats::{self, MacroCellTree};
+use gol_engines::{GoLEngine, Topology};
+
+const SEED: u64 = 42;
+
+/// Unbounded soups: side, density, generations, and the population and hash
+/// after them, recorded from `bench::naive_step`.
+const SOUPS: [(i64, f64, u64, u128, u64); 2] = [
+    (256, 0.5, 1 << 12, 3276, 0xb8c049dfa5d9ab36),
+    (1024, 0.5, 1 << 10, 51152, 0x45bdd9a09a958dee),
+];
+
+fn main() {
+    let soups = SOUPS.map(|(side, density, generations, population, hash)| {
+        let rle = bench::random_soup(side, density, SEED);
+        Case {
+            name: format!("soup_{}", side),
+            engine: Box::new(formats::load_pattern(&rle, Some("rle")).unwrap()),
+            generations,
+            topology: Topology::Unbounded,
+            population: Some(population),
+            hash: Some(hash),
+        }
+    });
+    // The torus wraps at the side of the engine's field, so the references
+    // are simulated at startup rather than recorded.
+    let tori = [(512, 0.5, 1 << 10), (2048, 0.5, 1 << 8)].map(|(side, density, generations)| {
+        let rle = bench::random_soup(side, density, SEED);
+        let mut engine = formats::load_pattern(&rle, Some("rle")).unwrap();
+        let initial = MacroCellTree::parse(&engine.save_as_macrocell())
+            .unwrap()
+            .cells();
+        let wrap = 1 << engine.side_length_log2();
+        let cells = bench::naive_torus(&initial, wrap, generations);
+        Case {
+            name: format!("dense_torus_{}", side),
+            engine: Box::new(engine),
+            generations,
+            topology: Topology::Torus,
+            population: Some(cells.len() as u128),
+            hash: Some(formats::hash_cells(&cells)),
+        }
+    });
+    bench::run_all(soups.into_iter().chain(tori));
+}
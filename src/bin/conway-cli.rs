@@ -0,0 +1,131 @@
+//! Headless runner: loads a pattern, advances it and prints the result as JSON.
+
+use conway::formats::{self, PatternFormat};
+use gol_engines::{GoLEngine, Topology};
+use std::path::PathBuf;
+
+const USAGE: &str = "\
+Usage: conway-cli <INPUT> [OPTIONS]
+
+Options:
+  --generations <N>   advance by exactly N generations (default: 0)
+  --steps-log2 <K>    advance by 2^K generations
+  --engine <NAME>     engine to run: default (default: default)
+  --topology <NAME>   unbounded or torus (default: unbounded)
+  --output <FILE>     save the result; the format is chosen by the extension:
+                      .mc, .rle, .cells, .lif (Life 1.05) or .life (Life 1.06)
+  --comment <TEXT>    comment line of the saved file; may be repeated";
+
+struct Args {
+    input: PathBuf,
+    generations: u64,
+    engine: String,
+    topology: Topology,
+    output: Option<(PathBuf, PatternFormat)>,
+    comments: Vec<String>,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut input = None;
+        let mut result = Self {
+            input: PathBuf::new(),
+            generations: 0,
+            engine: "default".to_owned(),
+            topology: Topology::Unbounded,
+            output: None,
+            comments: vec![],
+        };
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("missing value of {}", arg));
+            match arg.as_str() {
+                "--generations" => {
+                    result.generations = value()?
+                        .parse()
+                        .map_err(|err| format!("--generations: {}", err))?;
+                }
+                "--steps-log2" => {
+                    let steps_log2: u32 = value()?
+                        .parse()
+                        .map_err(|err| format!("--steps-log2: {}", err))?;
+                    result.generations = 1u64
+                        .checked_shl(steps_log2)
+                        .ok_or("--steps-log2 must be less than 64")?;
+                }
+                "--engine" => result.engine = value()?,
+                "--topology" => {
+                    result.topology = match value()?.as_str() {
+                        "unbounded" => Topology::Unbounded,
+                        "torus" => Topology::Torus,
+                        other => return Err(format!("unknown topology {}", other)),
+                    };
+                }
+                "--output" => {
+                    let output = PathBuf::from(value()?);
+                    let format = output
+                        .extension()
+                        .and_then(|x| x.to_str())
+                        .and_then(PatternFormat::from_extension)
+                        .ok_or(format!("{}: unknown output format", output.display()))?;
+                    result.output = Some((output, format));
+                }
+                "--comment" => result.comments.push(value()?),
+                _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+                _ if input.is_none() => input = Some(PathBuf::from(arg)),
+                _ => return Err(format!("unexpected argument {}", arg)),
+            }
+        }
+        result.input = input.ok_or("missing input file")?;
+        Ok(result)
+    }
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let data = std::fs::read(&args.input)
+        .map_err(|err| format!("failed to read {}: {}", args.input.display(), err))?;
+    let extension = args.input.extension().and_then(|x| x.to_str());
+    let (mut engine, origin): (Box<dyn GoLEngine>, _) = match args.engine.as_str() {
+        "default" => {
+            let (engine, origin) = formats::load_pattern_with_origin(&data, extension)?;
+            (Box::new(engine), origin)
+        }
+        other => return Err(format!("unknown engine {} (available: default)", other)),
+    };
+    let start_generation = formats::header_generation(&data).unwrap_or(0);
+
+    let timer = std::time::Instant::now();
+    let (generations, [dx, dy]) =
+        conway::advance(&mut *engine, args.generations, args.topology, |_| true);
+    let time = timer.elapsed().as_secs_f64();
+
+    if let Some((output, format)) = args.output {
+        let generation = start_generation + generations;
+        let origin = [origin[0] + dx, origin[1] + dy];
+        let data = formats::save_pattern(&mut *engine, format, generation, origin, &args.comments)?;
+        std::fs::write(&output, data)
+            .map_err(|err| format!("failed to write {}: {}", output.display(), err))?;
+    }
+
+    println!(
+        "{{\"generation\": {}, \"population\": {}, \"hash\": \"{:016x}\", \"time\": {}, \"dx\": {}, \"dy\": {}}}",
+        start_generation + generations,
+        conway::population(&mut *engine),
+        conway::hash(&mut *engine),
+        time,
+        dx,
+        dy,
+    );
+    Ok(())
+}
+
+fn main() {
+    if std::env::args().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", USAGE);
+        return;
+    }
+    let result = Args::parse(std::env::args().skip(1)).and_then(run);
+    if let Err(err) = result {
+        eprintln!("conway-cli: {}\n\n{}", err, USAGE);
+        std::process::exit(1);
+    }
+}
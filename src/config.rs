@@ -17,19 +17,17 @@ impl Config {
     pub const FILENAME_INPUT_FIELD_SIZE: [f32; 2] = [80., 20.];
 
     pub const WIDGET_GAP: f32 = 20.;
+    pub const SELECTION_STROKE_WIDTH: f32 = 2.;
+    pub const SELECTION_COLOR: Color32 = Color32::LIGHT_BLUE;
 
     pub const OTCA_DEPTH: u32 = 2;
     pub const MAX_FPS: f64 = 60.;
     pub const ZOOM_STEP: f32 = 1.5;
     pub const SUPERSAMPLING: f64 = 0.7;
-    pub const TOP_PATTERN: [[u8; 8]; 8] = [
-        [0, 1, 0, 0, 0, 0, 0, 0],
-        [0, 0, 1, 0, 0, 0, 0, 0],
-        [1, 1, 1, 0, 0, 0, 0, 0],
-        [0; 8],
-        [0; 8],
-        [0; 8],
-        [0; 8],
-        [0; 8],
-    ];
+    pub const HISTORY_DEPTH: usize = 100;
+    pub const HISTORY_MEMORY: usize = 1 << 30;
+    pub const UPDATE_BUDGET: f64 = 0.1;
+    pub const RANDOM_FILL_DENSITY: f64 = 0.5;
+    pub const MAX_RANDOM_FILL_AREA: i128 = 1 << 24;
+    pub const MAX_PASTE_PREVIEW_CELLS: usize = 10_000;
 }
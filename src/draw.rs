@@ -1,8 +1,9 @@
-use super::{field_source::FieldSource, App, BrightnessStrategy, Config};
+use super::{field_source::FieldSource, formats::PatternFormat, App, BrightnessStrategy, Config};
 use eframe::egui::{
-    load::SizedTexture, pos2, scroll_area::ScrollBarVisibility, Button, Color32, ColorImage,
-    Context, DragValue, Frame, Image, Margin, Rect, Response, RichText, ScrollArea, Slider, Stroke,
-    TextureFilter, TextureOptions, TextureWrapMode, Ui, Vec2,
+    load::SizedTexture, pos2, scroll_area::ScrollBarVisibility, Align2, Button, Color32,
+    ColorImage, Context, DragValue, Frame, Image, Margin, ProgressBar, Rect, Response, RichText,
+    ScrollArea, Slider, Spinner, Stroke, TextureFilter, TextureOptions, TextureWrapMode, Ui, Vec2,
+    Window,
 };
 use egui_file::{DialogType, FileDialog};
 use gol_engines::{DefaultEngine, GoLEngine, NiceInt, Topology};
@@ -29,7 +30,7 @@ impl App {
         button_text: &str,
         file_path: &mut Option<std::path::PathBuf>,
         file_dialog: &mut Option<FileDialog>,
-        extension: &'static str,
+        extensions: &'static [&'static str],
         dialog_type: DialogType,
     ) -> Response {
         ui.horizontal(|ui| {
@@ -38,7 +39,8 @@ impl App {
                 let filter = Box::new({
                     move |path: &std::path::Path| -> bool {
                         path.extension()
-                            .map_or(false, |x| x.to_str() == Some(extension))
+                            .and_then(|x| x.to_str())
+                            .is_some_and(|x| extensions.contains(&x))
                     }
                 });
                 let mut dialog = match dialog_type {
@@ -69,9 +71,32 @@ impl App {
     fn draw_viewport_controls(&mut self, ctx: &Context, ui: &mut Ui) {
         let text = if self.is_paused { "Play" } else { "Pause" };
         if ui.add(Self::new_button(text)).clicked() {
+            if self.is_paused {
+                self.record_history("Play");
+            }
             self.is_paused = !self.is_paused;
         }
 
+        if self.pending.is_some() {
+            let worker = &self.worker;
+            ui.horizontal(|ui| match worker.progress() {
+                Some(progress) => {
+                    if ui
+                        .add_enabled(!worker.is_cancelled(), Self::new_button("Cancel"))
+                        .clicked()
+                    {
+                        worker.cancel();
+                        self.is_paused = true;
+                    }
+                    ui.add(ProgressBar::new(progress).show_percentage());
+                }
+                None => {
+                    ui.add(Spinner::new());
+                    ui.label(Self::new_text("Loading the field"));
+                }
+            });
+        }
+
         ui.add_enabled(self.is_paused, |ui: &mut Ui| {
             ui.horizontal(|ui| {
                 ui.checkbox(
@@ -85,6 +110,7 @@ impl App {
             });
 
             if ui.add(Self::new_button("Next step")).clicked() {
+                self.record_history("Next step");
                 self.do_one_step = true;
             }
 
@@ -92,10 +118,23 @@ impl App {
                 ui.label(Self::new_text("Step size: 2^"));
                 ui.add(
                     DragValue::new(&mut self.simulation_steps_log2)
-                        .range(0..=self.life_engine.side_length_log2() - 1),
+                        .range(0..=self.side_length_log2 - 1),
                 )
             });
 
+            ui.horizontal(|ui: &mut Ui| {
+                let go = ui.add_enabled(
+                    self.pending.is_none() && self.target_generation > self.generation,
+                    Self::new_button("Go to generation:"),
+                );
+                if go.clicked() {
+                    self.record_history("Go to generation");
+                    self.advance(self.target_generation - self.generation);
+                }
+                // `DragValue` edits through `f64`: targets above 2^53 are rounded
+                ui.add(DragValue::new(&mut self.target_generation))
+            });
+
             ui.horizontal(|ui| {
                 ui.label(Self::new_text("Topology: "));
                 ui.radio_value(
@@ -106,19 +145,34 @@ impl App {
                 ui.radio_value(&mut self.topology, Topology::Torus, Self::new_text("Torus"))
             });
 
-            let response = Self::draw_file_dialog(
+            Self::draw_file_dialog(
                 ctx,
                 ui,
                 "Save as MacroCell",
                 &mut self.saved_file,
                 &mut self.save_file_dialog,
-                "mc",
+                &["mc"],
                 DialogType::SaveFile,
             );
             if let Some(file_path) = self.saved_file.take() {
-                let data = self.life_engine.save_as_macrocell();
-                std::fs::write(file_path, data).unwrap();
+                self.save(file_path, PatternFormat::MacroCell);
             }
+
+            let response = Self::draw_file_dialog(
+                ctx,
+                ui,
+                "Save as RLE",
+                &mut self.saved_rle_file,
+                &mut self.save_rle_file_dialog,
+                &["rle"],
+                DialogType::SaveFile,
+            );
+            if let Some(file_path) = self.saved_rle_file.take() {
+                self.save(file_path, PatternFormat::Rle);
+            }
+
+            ui.label(Self::new_text("Comments of saved files:"));
+            ui.text_edit_multiline(&mut self.save_comments);
             response
         });
 
@@ -135,6 +189,8 @@ impl App {
             self.reset_viewport();
         }
 
+        self.draw_selection_controls(ctx, ui);
+
         ui.add_space(Config::WIDGET_GAP);
 
         ui.label(Self::new_text("Recreate field: "));
@@ -147,58 +203,44 @@ impl App {
             );
             ui.radio_value(
                 &mut self.field_source,
-                FieldSource::FileMacroCell,
-                Self::new_text("MacroCell"),
-            );
-            ui.radio_value(
-                &mut self.field_source,
-                FieldSource::FileRLE,
-                Self::new_text("RLE"),
+                FieldSource::File,
+                Self::new_text("File"),
             );
         });
         match self.field_source {
             FieldSource::RecursiveOTCA => {
                 ui.horizontal(|ui| {
                     if ui.add(Self::new_button("Recreate with depth:")).clicked() {
-                        self.life_engine = Box::new(DefaultEngine::from_recursive_otca_metapixel(
-                            self.field_source_otca_depth,
-                            Config::TOP_PATTERN.iter().map(|row| row.to_vec()).collect(),
-                        ));
-                        self.reset_viewport();
+                        let depth = self.field_source_otca_depth;
+                        self.load(
+                            Box::new(move || {
+                                let engine = DefaultEngine::from_recursive_otca_metapixel(
+                                    depth,
+                                    conway::OTCA_TOP_PATTERN
+                                        .iter()
+                                        .map(|row| row.to_vec())
+                                        .collect(),
+                                );
+                                Ok((Box::new(engine), [0; 2]))
+                            }),
+                            0,
+                        );
                     }
                     ui.add(DragValue::new(&mut self.field_source_otca_depth).range(1..=5));
                 });
             }
-            FieldSource::FileMacroCell => {
-                Self::draw_file_dialog(
-                    ctx,
-                    ui,
-                    "Open MacroCell",
-                    &mut self.opened_file,
-                    &mut self.open_file_dialog,
-                    "mc",
-                    DialogType::OpenFile,
-                );
-                if let Some(file_path) = self.opened_file.take() {
-                    let data = std::fs::read(file_path).unwrap();
-                    self.life_engine = Box::new(DefaultEngine::from_macrocell(&data));
-                    self.reset_viewport();
-                }
-            }
-            FieldSource::FileRLE => {
+            FieldSource::File => {
                 Self::draw_file_dialog(
                     ctx,
                     ui,
-                    "Open RLE",
+                    "Open pattern",
                     &mut self.opened_file,
                     &mut self.open_file_dialog,
-                    "rle",
+                    &PatternFormat::EXTENSIONS,
                     DialogType::OpenFile,
                 );
                 if let Some(file_path) = self.opened_file.take() {
-                    let data = std::fs::read(file_path).unwrap();
-                    self.life_engine = Box::new(DefaultEngine::from_rle(&data));
-                    self.reset_viewport();
+                    self.open_file(&file_path);
                 }
             }
         }
@@ -210,12 +252,102 @@ impl App {
             NiceInt::from(self.generation)
         )));
 
+        ui.horizontal(|ui| {
+            ui.label(Self::new_text(&format!(
+                "Population as of generation {}:",
+                NiceInt::from(self.measure_generation)
+            )));
+            let button = Self::new_button("Measure");
+            if ui.add_enabled(self.measure_is_stale, button).clicked() {
+                self.measure_field();
+            }
+        });
+        ui.label(Self::new_text(&format!(
+            "{}",
+            NiceInt::from(self.measure.population as i128)
+        )));
+        let bounding_box = match self.measure.bounding_box {
+            Some([min, max]) => format!(
+                "\nx: {} .. {}\ny: {} .. {}",
+                NiceInt::from(min[0] as i128),
+                NiceInt::from(max[0] as i128),
+                NiceInt::from(min[1] as i128),
+                NiceInt::from(max[1] as i128)
+            ),
+            None => " empty".to_owned(),
+        };
+        ui.label(Self::new_text(&format!("Bounding box:{}", bounding_box)));
+
         ui.label(Self::new_text(&format!(
             "\nLast field update: {:.3} ms",
             NiceInt::from((self.last_update_duration * 1e3) as i128)
         )));
     }
 
+    fn draw_selection_controls(&mut self, ctx: &Context, ui: &mut Ui) {
+        if let Some(text) = self.copied.take() {
+            ctx.output_mut(|output| output.copied_text = text);
+        }
+
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.is_selecting, Self::new_text("Select"));
+            if ui
+                .add_enabled(self.selection.is_some(), Self::new_button("Deselect"))
+                .clicked()
+            {
+                self.selection = None;
+            }
+        });
+
+        let editable = self.selection.is_some() && self.pending.is_none();
+        ui.add_enabled_ui(editable, |ui| {
+            ui.horizontal(|ui| {
+                if ui.add(Self::new_button("Copy")).clicked() {
+                    self.copy_selection();
+                }
+                if ui.add(Self::new_button("Cut")).clicked() {
+                    self.cut_selection();
+                }
+                if ui.add(Self::new_button("Clear")).clicked() {
+                    self.clear_selection();
+                }
+                if ui.add(Self::new_button("Clear outside")).clicked() {
+                    self.clear_outside_selection();
+                }
+            });
+            ui.horizontal(|ui| {
+                if ui.add(Self::new_button("Rotate")).clicked() {
+                    self.rotate_selection();
+                }
+                if ui.add(Self::new_button("Flip horizontally")).clicked() {
+                    self.flip_selection(true);
+                }
+                if ui.add(Self::new_button("Flip vertically")).clicked() {
+                    self.flip_selection(false);
+                }
+            });
+            ui.horizontal(|ui| {
+                let fill =
+                    ui.add_enabled(self.can_fill_selection(), Self::new_button("Fill randomly"));
+                if fill.clicked() {
+                    self.fill_selection_randomly();
+                }
+                ui.label(Self::new_text(" density: "));
+                ui.add(
+                    DragValue::new(&mut self.random_fill_density)
+                        .range(0.0..=1.0)
+                        .speed(0.01),
+                );
+            });
+        });
+
+        let hint = match self.paste {
+            Some(_) => "Click to paste, Esc to cancel",
+            None => "Ctrl+V pastes RLE from the clipboard",
+        };
+        ui.label(Self::new_text(hint));
+    }
+
     fn draw_appearance_controls(&mut self, ui: &mut Ui) {
         ui.label(Self::new_text(&format!(
             "FPS: {:3}",
@@ -273,8 +405,88 @@ impl App {
         }
     }
 
+    fn draw_error(&mut self, ctx: &Context) {
+        let Some(message) = &self.error else {
+            return;
+        };
+        let mut closed = false;
+        Window::new("Error")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(Align2::CENTER_CENTER, [0., 0.])
+            .show(ctx, |ui| {
+                ui.label(message);
+                closed = ui.button("OK").clicked();
+            });
+        if closed {
+            self.error = None;
+        }
+    }
+
+    fn draw_history(&mut self, ui: &mut Ui) {
+        let idle = self.pending.is_none();
+        ui.horizontal(|ui| {
+            let undo = Self::new_button("Undo");
+            if ui
+                .add_enabled(idle && self.history.can_undo(), undo)
+                .clicked()
+            {
+                self.undo(1);
+            }
+            let redo = Self::new_button("Redo");
+            if ui
+                .add_enabled(idle && self.history.can_redo(), redo)
+                .clicked()
+            {
+                self.redo();
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label(Self::new_text("Depth: "));
+            if ui
+                .add(DragValue::new(&mut self.history.depth).range(1..=10_000))
+                .changed()
+            {
+                self.history.truncate();
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label(Self::new_text(&format!(
+                "Memory: {} of ",
+                self.history.memory() >> 20
+            )));
+            let mut limit = self.history.memory_limit >> 20;
+            if ui
+                .add(DragValue::new(&mut limit).range(1..=1 << 20))
+                .changed()
+            {
+                self.history.memory_limit = limit << 20;
+                self.history.truncate();
+            }
+            ui.label(Self::new_text(" MiB"));
+        });
+
+        let mut steps = None;
+        for (i, snapshot) in self.history.undo_entries().rev().enumerate() {
+            let text = format!(
+                "{} at generation {}",
+                snapshot.label,
+                NiceInt::from(snapshot.generation)
+            );
+            if ui.add_enabled(idle, Self::new_button(&text)).clicked() {
+                steps = Some(i + 1);
+            }
+        }
+        if let Some(steps) = steps {
+            self.undo(steps);
+        }
+    }
+
     fn draw_statistics(&mut self, ui: &mut Ui) {
-        ui.label(Self::new_text(&self.life_engine.statistics()));
+        if let Some(engine) = &mut self.life_engine {
+            self.statistics = engine.statistics();
+        }
+        ui.label(Self::new_text(&self.statistics));
     }
 
     fn draw_controls(&mut self, ctx: &Context, ui: &mut Ui) {
@@ -284,6 +496,10 @@ impl App {
             ui.horizontal(|ui| {
                 ui.vertical(|ui| {
                     self.draw_viewport_controls(ctx, ui);
+
+                    ui.add_space(Config::WIDGET_GAP);
+
+                    self.draw_history(ui);
                 });
 
                 // to adjust the bounds
@@ -306,49 +522,85 @@ impl App {
     }
 
     fn draw_gol_field(&mut self, ui: &mut Ui, size_px: f32) {
-        // Retrieving a part of the field that slightly exceeds viewport.
-        // desired size of texture in pixels
-        let mut resolution = size_px as f64 * self.supersampling;
-        // top left viewport coordinate in cells
-        let (mut x, mut y) = (self.viewport_pos_x, self.viewport_pos_y);
-        // size of the subregion of the field that will be retrieved;
-        // is going to be increased from `viewport_size`
-        let mut size = self.viewport_size;
-        // `step_size` is the number of cells per pixel side
-        self.life_engine.fill_texture(
-            &mut x,
-            &mut y,
-            &mut size,
-            &mut resolution,
-            &mut self.viewport_buf,
-        );
-
-        let gray = self.brightness_strategy.transform(
-            resolution as usize,
-            &self.viewport_buf,
-            self.brightness_shift,
-        );
-
-        let ci = ColorImage::from_gray([resolution as usize; 2], &gray);
-        let texture_options = TextureOptions {
-            magnification: TextureFilter::Nearest,
-            minification: TextureFilter::Linear,
-            wrap_mode: TextureWrapMode::ClampToEdge,
-        };
-        self.texture.set(ci, texture_options);
-        let vp_x = (self.viewport_pos_x - x) / size;
-        let vp_y = (self.viewport_pos_y - y) / size;
+        // While the engine is busy, the texture of the last finished generation is shown.
+        if let Some(engine) = &mut self.life_engine {
+            // Retrieving a part of the field that slightly exceeds viewport.
+            // desired size of texture in pixels
+            let mut resolution = size_px as f64 * self.supersampling;
+            // top left viewport coordinate in cells
+            let (mut x, mut y) = (self.viewport_pos_x, self.viewport_pos_y);
+            // size of the subregion of the field that will be retrieved;
+            // is going to be increased from `viewport_size`
+            let mut size = self.viewport_size;
+            // `step_size` is the number of cells per pixel side
+            engine.fill_texture(
+                &mut x,
+                &mut y,
+                &mut size,
+                &mut resolution,
+                &mut self.viewport_buf,
+            );
+
+            let gray = self.brightness_strategy.transform(
+                resolution as usize,
+                &self.viewport_buf,
+                self.brightness_shift,
+            );
+
+            let ci = ColorImage::from_gray([resolution as usize; 2], &gray);
+            let texture_options = TextureOptions {
+                magnification: TextureFilter::Nearest,
+                minification: TextureFilter::Linear,
+                wrap_mode: TextureWrapMode::ClampToEdge,
+            };
+            self.texture.set(ci, texture_options);
+            (self.texture_pos_x, self.texture_pos_y, self.texture_size) = (x, y, size);
+        }
+        let vp_x = (self.viewport_pos_x - self.texture_pos_x) / self.texture_size;
+        let vp_y = (self.viewport_pos_y - self.texture_pos_y) / self.texture_size;
         let vp = pos2(vp_x as f32, vp_y as f32);
-        let vp_s = Vec2::splat((self.viewport_size / size) as f32);
+        let vp_s = Vec2::splat((self.viewport_size / self.texture_size) as f32);
 
         let source = SizedTexture::new(self.texture.id(), [size_px; 2]);
         let uv = Rect::from_points(&[vp, vp + vp_s]);
         let image = Image::from_texture(source).uv(uv);
         let response = ui.add(image);
         self.life_rect.replace(response.rect);
+
+        let rect = response.rect;
+        let painter = ui.painter().with_clip_rect(rect);
+        let to_screen = |x: i64, y: i64| {
+            let p = [
+                (x as f64 - self.viewport_pos_x) / self.viewport_size,
+                (y as f64 - self.viewport_pos_y) / self.viewport_size,
+            ];
+            rect.left_top() + rect.size() * Vec2::new(p[0] as f32, p[1] as f32)
+        };
+        let stroke = Stroke::new(Config::SELECTION_STROKE_WIDTH, Config::SELECTION_COLOR);
+        if let Some([min, max]) = self.selection_bounds() {
+            let selection =
+                Rect::from_two_pos(to_screen(min[0], min[1]), to_screen(max[0] + 1, max[1] + 1));
+            painter.rect_stroke(selection, 0., stroke);
+        }
+        if let (Some(cells), Some([width, height])) = (&self.paste, self.paste_size()) {
+            let [x0, y0] = self.paste_cell;
+            let outline = Rect::from_two_pos(to_screen(x0, y0), to_screen(x0 + width, y0 + height));
+            painter.rect_stroke(outline, 0., stroke);
+            if cells.len() <= Config::MAX_PASTE_PREVIEW_CELLS {
+                for &(x, y) in cells {
+                    let cell = Rect::from_two_pos(
+                        to_screen(x0 + x, y0 + y),
+                        to_screen(x0 + x + 1, y0 + y + 1),
+                    );
+                    painter.rect_filled(cell, 0., Config::SELECTION_COLOR);
+                }
+            }
+        }
     }
 
     pub fn draw(&mut self, ctx: &Context, ui: &mut Ui) {
+        self.draw_error(ctx);
+
         let area = ui.available_size();
 
         let size_px = area
@@ -1,6 +1,5 @@
 #[derive(PartialEq, Eq)]
 pub enum FieldSource {
-    FileRLE,
-    FileMacroCell,
+    File, // Pattern file of any supported format.
     RecursiveOTCA,
 }
@@ -0,0 +1,1184 @@
+//! Pattern file formats on top of the engines' MacroCell and RLE loaders.
+//!
+//! Plaintext and Life 1.05/1.06 patterns are converted to RLE before loading.
+
+use gol_engines::{DefaultEngine, GoLEngine};
+use std::panic::AssertUnwindSafe;
+
+/// Pattern file formats that can be opened.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PatternFormat {
+    MacroCell,
+    Rle,
+    Plaintext, // `.cells`
+    Life105,
+    Life106,
+}
+
+impl PatternFormat {
+    pub const EXTENSIONS: [&'static str; 5] = ["mc", "rle", "cells", "lif", "life"];
+
+    /// Detects the format by the content, falling back to the file extension.
+    pub fn detect(data: &[u8], extension: Option<&str>) -> Option<Self> {
+        let text = String::from_utf8_lossy(data);
+        let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+        let first_line = lines.clone().next().unwrap_or("").trim_end();
+        if first_line.starts_with("[M2]") {
+            return Some(Self::MacroCell);
+        }
+        if first_line.starts_with("#Life 1.05") {
+            return Some(Self::Life105);
+        }
+        if first_line.starts_with("#Life 1.06") {
+            return Some(Self::Life106);
+        }
+        if first_line.starts_with('!') {
+            return Some(Self::Plaintext);
+        }
+        let header = lines.find(|line| !line.starts_with('#'));
+        let is_rle_header = |line: &str| {
+            let rest = line.trim_start().strip_prefix('x');
+            rest.is_some_and(|rest| rest.trim_start().starts_with('='))
+        };
+        if header.is_some_and(is_rle_header) {
+            return Some(Self::Rle);
+        }
+        Self::from_extension(extension?)
+    }
+
+    /// Format of a file by its extension; `.lif` is taken for Life 1.05 and
+    /// `.life` for Life 1.06.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "mc" => Some(Self::MacroCell),
+            "rle" => Some(Self::Rle),
+            "cells" => Some(Self::Plaintext),
+            "lif" => Some(Self::Life105),
+            "life" => Some(Self::Life106),
+            _ => None,
+        }
+    }
+}
+
+/// Failure to parse a pattern file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,   // 1-based; 0 if the failure is not tied to a position.
+    pub column: usize, // 1-based, in characters.
+    pub kind: ParseErrorKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    UnknownFormat,
+    InvalidUtf8,
+    UnexpectedCharacter(char),
+    MissingHeader,       // No `x = , y = ` line in an RLE file.
+    MalformedHeader,     // The `x = , y = ` line of an RLE file.
+    MalformedNode,       // A MacroCell node.
+    MalformedPosition,   // A Life 1.05 `#P` line.
+    ExpectedCoordinates, // A Life 1.06 line.
+    UnsupportedRule(String),
+    Rejected(String), // Panic message of an engine loader.
+}
+
+impl ParseError {
+    /// Error at 0-based `line_index` and `column_index`.
+    fn at(line_index: usize, column_index: usize, kind: ParseErrorKind) -> Self {
+        Self {
+            line: line_index + 1,
+            column: column_index + 1,
+            kind,
+        }
+    }
+
+    fn whole_file(kind: ParseErrorKind) -> Self {
+        Self {
+            line: 0,
+            column: 0,
+            kind,
+        }
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.line != 0 {
+            write!(f, "line {}, column {}: ", self.line, self.column)?;
+        }
+        match &self.kind {
+            ParseErrorKind::UnknownFormat => write!(f, "unknown pattern format"),
+            ParseErrorKind::InvalidUtf8 => write!(f, "invalid UTF-8"),
+            ParseErrorKind::UnexpectedCharacter(c) => write!(f, "unexpected character {:?}", c),
+            ParseErrorKind::MissingHeader => write!(f, "missing `x = , y = ` header line"),
+            ParseErrorKind::MalformedHeader => write!(f, "malformed `x = , y = ` header line"),
+            ParseErrorKind::MalformedNode => write!(f, "malformed node"),
+            ParseErrorKind::MalformedPosition => write!(f, "malformed #P line"),
+            ParseErrorKind::ExpectedCoordinates => write!(f, "expected two coordinates"),
+            ParseErrorKind::UnsupportedRule(rule) => {
+                write!(f, "unsupported rule {}, only B3/S23 is available", rule)
+            }
+            ParseErrorKind::Rejected(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl From<ParseError> for String {
+    fn from(err: ParseError) -> Self {
+        err.to_string()
+    }
+}
+
+/// The text of a file, failing at the position of the first invalid byte.
+fn utf8(data: &[u8]) -> Result<&str, ParseError> {
+    std::str::from_utf8(data).map_err(|err| {
+        let valid = String::from_utf8_lossy(&data[..err.valid_up_to()]);
+        let line = valid.matches('\n').count();
+        let column = valid.rsplit('\n').next().unwrap_or("").chars().count();
+        ParseError::at(line, column, ParseErrorKind::InvalidUtf8)
+    })
+}
+
+/// Loads a pattern of any supported format with the engines' loaders.
+///
+/// The input is fully parsed first, so that malformed files are reported
+/// with their position rather than by a panic of the loaders.
+pub fn load_pattern(data: &[u8], extension: Option<&str>) -> Result<DefaultEngine, ParseError> {
+    load_pattern_with_origin(data, extension).map(|(engine, _)| engine)
+}
+
+/// Like `load_pattern`, also returning the cell of the field at the file's
+/// `(0, 0)`: the position of a Golly extended RLE `#CXRLE Pos=x,y` line, the
+/// `#P` lines of Life 1.05 and the coordinates of Life 1.06 are kept this
+/// way, whereas the loaders place the pattern themselves.
+pub fn load_pattern_with_origin(
+    data: &[u8],
+    extension: Option<&str>,
+) -> Result<(DefaultEngine, [i64; 2]), ParseError> {
+    let format = PatternFormat::detect(data, extension)
+        .ok_or(ParseError::whole_file(ParseErrorKind::UnknownFormat))?;
+    let (rle, corner) = match format {
+        PatternFormat::MacroCell => {
+            let tree = MacroCellTree::parse(data)?;
+            (vec![], tree.bounding_box().map(|[min, _]| min))
+        }
+        _ => {
+            let mut cells = parse_cells(data, format)?;
+            let x0 = cells.iter().map(|c| c.0).min();
+            let y0 = cells.iter().map(|c| c.1).min();
+            (
+                cells_to_rle(&mut cells),
+                x0.zip(y0).map(|(x0, y0)| [x0, y0]),
+            )
+        }
+    };
+    let loader = || match format {
+        PatternFormat::MacroCell => DefaultEngine::from_macrocell(data),
+        _ => DefaultEngine::from_rle(&rle),
+    };
+    // the checks above should leave the loaders nothing to panic on
+    let mut engine = std::panic::catch_unwind(AssertUnwindSafe(loader)).map_err(|payload| {
+        ParseError::whole_file(ParseErrorKind::Rejected(super::panic_message(&*payload)))
+    })?;
+
+    let Some(corner) = corner else {
+        return Ok((engine, [0; 2]));
+    };
+    let loaded = MacroCellTree::parse(&engine.save_as_macrocell())?.bounding_box();
+    let origin = match loaded {
+        Some([min, _]) => [min[0] - corner[0], min[1] - corner[1]],
+        None => [0; 2],
+    };
+    Ok((engine, origin))
+}
+
+/// Live cells of a pattern of any format but MacroCell in the file's own
+/// coordinates.
+fn parse_cells(data: &[u8], format: PatternFormat) -> Result<Vec<(i64, i64)>, ParseError> {
+    let text = utf8(data)?;
+    match format {
+        PatternFormat::Rle => {
+            let [x0, y0] = header_position(data).unwrap_or([0; 2]);
+            let cells = parse_rle(text)?;
+            Ok(cells.into_iter().map(|(x, y)| (x0 + x, y0 + y)).collect())
+        }
+        PatternFormat::Plaintext => parse_plaintext(text),
+        PatternFormat::Life105 => parse_life105(text),
+        PatternFormat::Life106 => parse_life106(text),
+        PatternFormat::MacroCell => unreachable!("MacroCell is loaded as a tree"),
+    }
+}
+
+/// Live cells of the field relative to `origin`, see
+/// `load_pattern_with_origin`.
+fn field_cells(engine: &mut dyn GoLEngine, origin: [i64; 2]) -> Result<Vec<(i64, i64)>, String> {
+    let cells = MacroCellTree::parse(&engine.save_as_macrocell())?.cells();
+    Ok(cells
+        .into_iter()
+        .map(|(x, y)| (x - origin[0], y - origin[1]))
+        .collect())
+}
+
+/// Saves the live cells of the field as RLE of their bounding box, with the
+/// Golly extended header `#CXRLE Pos=x,y Gen=n` relative to `origin` and a
+/// `#C` line for every comment.
+pub fn save_as_rle(
+    engine: &mut dyn GoLEngine,
+    generation: u64,
+    origin: [i64; 2],
+    comments: &[String],
+) -> Result<Vec<u8>, String> {
+    let mut cells = field_cells(engine, origin)?;
+    let x0 = cells.iter().map(|c| c.0).min().unwrap_or(0);
+    let y0 = cells.iter().map(|c| c.1).min().unwrap_or(0);
+    let mut rle = format!("#CXRLE Pos={},{} Gen={}\n", x0, y0, generation);
+    for comment in comments {
+        rle += &format!("#C {}\n", comment);
+    }
+    let mut rle = rle.into_bytes();
+    rle.extend(cells_to_rle(&mut cells));
+    Ok(rle)
+}
+
+/// Saves the live cells inside the rectangle with corners `min` and `max`,
+/// both included, as RLE of their bounding box.
+pub fn region_as_rle(
+    engine: &mut dyn GoLEngine,
+    min: [i64; 2],
+    max: [i64; 2],
+) -> Result<Vec<u8>, String> {
+    let mut cells = MacroCellTree::parse(&engine.save_as_macrocell())?.cells_in(min, max);
+    Ok(cells_to_rle(&mut cells))
+}
+
+/// Encodes the field in `format`; `comments` are written to MacroCell, RLE
+/// and plaintext files. Positions are written relative to `origin`, except in
+/// MacroCell, which keeps the coordinates of the engine's root node.
+pub fn save_pattern(
+    engine: &mut dyn GoLEngine,
+    format: PatternFormat,
+    generation: u64,
+    origin: [i64; 2],
+    comments: &[String],
+) -> Result<Vec<u8>, String> {
+    let write = match format {
+        PatternFormat::MacroCell => {
+            return Ok(with_macrocell_header(
+                engine.save_as_macrocell(),
+                generation,
+                comments,
+            ))
+        }
+        PatternFormat::Rle => return save_as_rle(engine, generation, origin, comments),
+        PatternFormat::Plaintext => {
+            return Ok(cells_to_plaintext(
+                &mut field_cells(engine, origin)?,
+                comments,
+            ))
+        }
+        PatternFormat::Life105 => cells_to_life105,
+        PatternFormat::Life106 => cells_to_life106,
+    };
+    Ok(write(&mut field_cells(engine, origin)?))
+}
+
+/// Position of the top-left corner of the pattern's box stored in the Golly
+/// extended RLE line `#CXRLE Pos=x,y Gen=n`.
+pub fn header_position(data: &[u8]) -> Option<[i64; 2]> {
+    let text = std::str::from_utf8(data).ok()?;
+    header_lines(text).find_map(|line| {
+        let (x, y) = line
+            .strip_prefix("#CXRLE")?
+            .split_whitespace()
+            .find_map(|field| field.strip_prefix("Pos="))?
+            .split_once(',')?;
+        Some([x.trim().parse().ok()?, y.trim().parse().ok()?])
+    })
+}
+
+/// Generation count stored in the header: `#G n` of MacroCell or `Gen=n` of
+/// the Golly extended RLE line `#CXRLE Pos=x,y Gen=n`.
+pub fn header_generation(data: &[u8]) -> Option<u64> {
+    let text = std::str::from_utf8(data).ok()?;
+    header_lines(text).find_map(|line| {
+        if let Some(gen) = line.strip_prefix("#G") {
+            return gen.trim().parse().ok();
+        }
+        line.strip_prefix("#CXRLE")?
+            .split_whitespace()
+            .find_map(|field| field.strip_prefix("Gen="))?
+            .parse()
+            .ok()
+    })
+}
+
+/// Fails unless `rule`, found at the 0-based position, is B3/S23, which is
+/// the only rule the engines run.
+fn check_rule(rule: &str, line_index: usize, column_index: usize) -> Result<(), ParseError> {
+    let indent = rule.chars().take_while(|c| c.is_whitespace()).count();
+    let rule = rule.trim();
+    match rule.to_ascii_lowercase().as_str() {
+        "b3/s23" | "b3s23" | "s23/b3" | "23/3" | "life" => Ok(()),
+        _ => Err(ParseError::at(
+            line_index,
+            column_index + indent,
+            ParseErrorKind::UnsupportedRule(rule.to_owned()),
+        )),
+    }
+}
+
+/// Adds the `#R` and `#G` lines and a `#C` line for every comment to a
+/// MacroCell file written by the engine.
+pub fn with_macrocell_header(data: Vec<u8>, generation: u64, comments: &[String]) -> Vec<u8> {
+    let text = String::from_utf8_lossy(&data);
+    let Some((first_line, body)) = text.split_once('\n') else {
+        return data;
+    };
+    let mut result = format!("{}\n", first_line);
+    if !header_lines(&text).any(|line| line.starts_with("#R")) {
+        result += "#R B3/S23\n";
+    }
+    if !header_lines(&text).any(|line| line.starts_with("#G")) {
+        result += &format!("#G {}\n", generation);
+    }
+    for comment in comments {
+        result += &format!("#C {}\n", comment);
+    }
+    result += body;
+    result.into_bytes()
+}
+
+/// Comment lines at the top of a file, after the MacroCell `[M2]` line.
+fn header_lines(text: &str) -> impl Iterator<Item = &str> {
+    text.lines()
+        .skip_while(|line| line.starts_with("[M2]"))
+        .take_while(|line| line.starts_with('#'))
+}
+
+/// Live cells of an RLE pattern relative to the top-left corner of the box
+/// given by its header; fails on a rule other than B3/S23.
+///
+/// `#` lines are comments; in particular `#R x y` is the position of the
+/// pattern, not its rule.
+pub fn parse_rle(text: &str) -> Result<Vec<(i64, i64)>, ParseError> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.starts_with('#') && !line.trim().is_empty());
+    let (header_index, header) = lines
+        .next()
+        .ok_or(ParseError::whole_file(ParseErrorKind::MissingHeader))?;
+    parse_rle_header(header, header_index)?;
+
+    let mut cells = vec![];
+    let (mut x, mut y) = (0i64, 0i64);
+    let mut count: Option<i64> = None;
+    for (line_index, line) in lines {
+        for (column, c) in line.chars().enumerate() {
+            let unexpected =
+                || ParseError::at(line_index, column, ParseErrorKind::UnexpectedCharacter(c));
+            if let Some(digit) = c.to_digit(10) {
+                let n = count.unwrap_or(0);
+                count = Some(
+                    n.checked_mul(10)
+                        .and_then(|n| n.checked_add(digit as i64))
+                        .ok_or_else(unexpected)?,
+                );
+                continue;
+            }
+            let n = count.take().unwrap_or(1);
+            match c {
+                'b' | '.' => x += n,
+                'o' | 'A' => {
+                    cells.extend((x..x + n).map(|cx| (cx, y)));
+                    x += n;
+                }
+                '$' => (x, y) = (0, y + n),
+                '!' => return Ok(cells),
+                _ if c.is_whitespace() => {}
+                _ => return Err(unexpected()),
+            }
+        }
+    }
+    Ok(cells)
+}
+
+/// Checks the `x = m, y = n, rule = r` line of an RLE file.
+fn parse_rle_header(header: &str, line_index: usize) -> Result<(), ParseError> {
+    let malformed = |column| ParseError::at(line_index, column, ParseErrorKind::MalformedHeader);
+    let mut size = [None; 2];
+    let mut column = 0;
+    for field in header.split(',') {
+        let (key, value) = field.split_once('=').ok_or(malformed(column))?;
+        let value_column = column + key.chars().count() + 1;
+        match key.trim() {
+            "x" => size[0] = value.trim().parse::<u64>().ok(),
+            "y" => size[1] = value.trim().parse::<u64>().ok(),
+            "rule" => check_rule(value, line_index, value_column)?,
+            _ => {}
+        }
+        column += field.chars().count() + 1;
+    }
+    match size {
+        [Some(_), Some(_)] => Ok(()),
+        _ => Err(malformed(0)),
+    }
+}
+
+/// Appends the live cells of a row of `dead`/`alive` characters.
+fn parse_row(
+    line: &str,
+    line_index: usize,
+    (x, y): (i64, i64),
+    [dead, alive]: [char; 2],
+    cells: &mut Vec<(i64, i64)>,
+) -> Result<(), ParseError> {
+    for (column, c) in line.trim_end().chars().enumerate() {
+        if c == alive {
+            cells.push((x + column as i64, y));
+        } else if c != dead {
+            return Err(ParseError::at(
+                line_index,
+                column,
+                ParseErrorKind::UnexpectedCharacter(c),
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn parse_plaintext(text: &str) -> Result<Vec<(i64, i64)>, ParseError> {
+    let mut cells = vec![];
+    let rows = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.starts_with('!'));
+    for (y, (line_index, line)) in rows.enumerate() {
+        let alive = if line.contains('*') { '*' } else { 'O' };
+        parse_row(line, line_index, (0, y as i64), ['.', alive], &mut cells)?;
+    }
+    Ok(cells)
+}
+
+fn parse_life105(text: &str) -> Result<Vec<(i64, i64)>, ParseError> {
+    let mut cells = vec![];
+    let (mut x, mut y) = (0, 0);
+    for (line_index, line) in text.lines().enumerate() {
+        if let Some(position) = line.strip_prefix("#P") {
+            let numbers = position
+                .split_whitespace()
+                .map(str::parse)
+                .collect::<Result<Vec<i64>, _>>();
+            match numbers.as_deref() {
+                Ok(&[px, py]) => (x, y) = (px, py),
+                _ => {
+                    return Err(ParseError::at(
+                        line_index,
+                        0,
+                        ParseErrorKind::MalformedPosition,
+                    ))
+                }
+            }
+        } else if let Some(rule) = line.strip_prefix("#R") {
+            check_rule(rule, line_index, 2)?;
+        } else if !line.starts_with('#') {
+            parse_row(line, line_index, (x, y), ['.', '*'], &mut cells)?;
+            y += 1;
+        }
+    }
+    Ok(cells)
+}
+
+fn parse_life106(text: &str) -> Result<Vec<(i64, i64)>, ParseError> {
+    let mut cells = vec![];
+    for (line_index, line) in text.lines().enumerate() {
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        let numbers = line
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<Result<Vec<i64>, _>>();
+        match numbers.as_deref() {
+            Ok(&[x, y]) => cells.push((x, y)),
+            _ => {
+                return Err(ParseError::at(
+                    line_index,
+                    0,
+                    ParseErrorKind::ExpectedCoordinates,
+                ))
+            }
+        }
+    }
+    Ok(cells)
+}
+
+/// Encodes the cells' bounding box as RLE with lines of at most 70 characters.
+pub fn cells_to_rle(cells: &mut Vec<(i64, i64)>) -> Vec<u8> {
+    const LINE_WIDTH: usize = 70;
+
+    if cells.is_empty() {
+        return b"x = 1, y = 1, rule = B3/S23\nb!\n".to_vec();
+    }
+    cells.sort_unstable_by_key(|&(x, y)| (y, x));
+    cells.dedup();
+    let x0 = cells.iter().map(|c| c.0).min().unwrap();
+    let x1 = cells.iter().map(|c| c.0).max().unwrap();
+    let (y0, y1) = (cells[0].1, cells[cells.len() - 1].1);
+
+    let mut tokens = vec![];
+    let push = |tokens: &mut Vec<String>, count: i64, tag: char| match count {
+        0 => {}
+        1 => tokens.push(tag.to_string()),
+        _ => tokens.push(format!("{}{}", count, tag)),
+    };
+    let (mut x, mut y) = (x0, y0);
+    let mut run = 0;
+    for &(cx, cy) in cells.iter() {
+        if cy != y {
+            push(&mut tokens, run, 'o');
+            push(&mut tokens, cy - y, '$');
+            (x, y, run) = (x0, cy, 0);
+        }
+        if cx != x + run {
+            push(&mut tokens, run, 'o');
+            push(&mut tokens, cx - x - run, 'b');
+            (x, run) = (cx, 0);
+        }
+        run += 1;
+    }
+    push(&mut tokens, run, 'o');
+    tokens.push("!".to_owned());
+
+    let mut rle = format!("x = {}, y = {}, rule = B3/S23\n", x1 - x0 + 1, y1 - y0 + 1);
+    let mut line_len = 0;
+    for token in tokens {
+        if line_len + token.len() > LINE_WIDTH {
+            rle.push('\n');
+            line_len = 0;
+        }
+        line_len += token.len();
+        rle += &token;
+    }
+    rle.push('\n');
+    rle.into_bytes()
+}
+
+/// Writes every comment as a `!` line, or a bare `!` without comments, so
+/// that even an empty field is recognised.
+fn cells_to_plaintext(cells: &mut Vec<(i64, i64)>, comments: &[String]) -> Vec<u8> {
+    let (_, rows) = cells_to_rows(cells, ['.', 'O']);
+    let mut text = String::new();
+    for comment in comments {
+        text += &format!("!{}\n", comment);
+    }
+    if comments.is_empty() {
+        text.push_str("!\n");
+    }
+    (text + &rows).into_bytes()
+}
+
+/// Writes a single `#P` block at the top-left corner of the bounding box.
+fn cells_to_life105(cells: &mut Vec<(i64, i64)>) -> Vec<u8> {
+    let ((x0, y0), rows) = cells_to_rows(cells, ['.', '*']);
+    format!("#Life 1.05\n#N\n#P {} {}\n{}", x0, y0, rows).into_bytes()
+}
+
+fn cells_to_life106(cells: &mut Vec<(i64, i64)>) -> Vec<u8> {
+    cells.sort_unstable_by_key(|&(x, y)| (y, x));
+    cells.dedup();
+    let mut text = "#Life 1.06\n".to_owned();
+    for (x, y) in cells.iter() {
+        text += &format!("{} {}\n", x, y);
+    }
+    text.into_bytes()
+}
+
+/// Rows of the cells' bounding box as `dead`/`alive` characters without
+/// trailing dead cells, and the top-left corner of the box.
+fn cells_to_rows(cells: &mut Vec<(i64, i64)>, [dead, alive]: [char; 2]) -> ((i64, i64), String) {
+    let mut rows = String::new();
+    if cells.is_empty() {
+        return ((0, 0), rows);
+    }
+    cells.sort_unstable_by_key(|&(x, y)| (y, x));
+    cells.dedup();
+    let x0 = cells.iter().map(|c| c.0).min().unwrap();
+    let y0 = cells[0].1;
+
+    let (mut x, mut y) = (x0, y0);
+    for &(cx, cy) in cells.iter() {
+        if cy != y {
+            rows.push('\n');
+            for _ in y + 1..cy {
+                rows.push(dead);
+                rows.push('\n');
+            }
+            (x, y) = (x0, cy);
+        }
+        rows.extend(std::iter::repeat_n(dead, (cx - x) as usize));
+        rows.push(alive);
+        x = cx + 1;
+    }
+    rows.push('\n');
+    ((x0, y0), rows)
+}
+
+/// Quadtree of a two-state MacroCell file, as written by `save_as_macrocell`.
+pub struct MacroCellTree {
+    nodes: Vec<Node>, // Node `i` is referred to as `i + 1`, `0` is the empty node.
+}
+
+#[derive(Clone)]
+enum Node {
+    Leaf(Vec<(i64, i64)>), // Live cells of an 8x8 leaf.
+    Inner(u32, [usize; 4]),
+}
+
+impl MacroCellTree {
+    /// Parses and checks the tree: the levels of the nodes must match, and
+    /// the `#R` line must name B3/S23.
+    pub fn parse(data: &[u8]) -> Result<Self, ParseError> {
+        let text = utf8(data)?;
+        let mut nodes = vec![];
+        let mut levels = vec![0]; // of the nodes, matching any level for the empty node
+        for (line_index, line) in text.lines().enumerate() {
+            if let Some(rule) = line.strip_prefix("#R") {
+                check_rule(rule, line_index, 2)?;
+            }
+            if line.is_empty() || line.starts_with('[') || line.starts_with('#') {
+                continue;
+            }
+            let malformed = ParseError::at(line_index, 0, ParseErrorKind::MalformedNode);
+            let node = if line.starts_with(|c: char| c.is_ascii_digit()) {
+                let numbers = line
+                    .split_whitespace()
+                    .map(str::parse)
+                    .collect::<Result<Vec<usize>, _>>();
+                match numbers.as_deref() {
+                    Ok(&[level, nw, ne, sw, se])
+                        if (4..=62).contains(&level)
+                            && [nw, ne, sw, se].iter().all(|&i| {
+                                i <= nodes.len() && (i == 0 || levels[i] == level - 1)
+                            }) =>
+                    {
+                        Node::Inner(level as u32, [nw, ne, sw, se])
+                    }
+                    _ => return Err(malformed),
+                }
+            } else {
+                let mut leaf = vec![];
+                let (mut row, mut column) = (0, 0);
+                for (i, c) in line.chars().enumerate() {
+                    let at = |kind| ParseError::at(line_index, i, kind);
+                    match c {
+                        '$' => (row, column) = (row + 1, 0),
+                        _ if row >= 8 || column >= 8 => {
+                            return Err(at(ParseErrorKind::MalformedNode))
+                        }
+                        '*' => {
+                            leaf.push((column, row));
+                            column += 1;
+                        }
+                        '.' => column += 1,
+                        _ => return Err(at(ParseErrorKind::UnexpectedCharacter(c))),
+                    }
+                }
+                Node::Leaf(leaf)
+            };
+            levels.push(match node {
+                Node::Leaf(_) => 3,
+                Node::Inner(level, _) => level as usize,
+            });
+            nodes.push(node);
+        }
+        Ok(Self { nodes })
+    }
+
+    /// Exact number of live cells; each node is counted once.
+    pub fn population(&self) -> u128 {
+        let mut populations: Vec<u128> = vec![0]; // of the empty node
+        for node in &self.nodes {
+            let population = match node {
+                Node::Leaf(leaf) => leaf.len() as u128,
+                Node::Inner(_, children) => children.iter().map(|&i| populations[i]).sum(),
+            };
+            populations.push(population);
+        }
+        populations[self.nodes.len()]
+    }
+
+    /// Top-left and bottom-right live cells in the coordinates of the root
+    /// node; each node is visited once.
+    pub fn bounding_box(&self) -> Option<[[i64; 2]; 2]> {
+        let mut boxes: Vec<Option<[[i64; 2]; 2]>> = vec![None]; // of the empty node
+        for node in &self.nodes {
+            let bounding_box = match node {
+                Node::Leaf(leaf) => leaf.iter().fold(None, |b, &(x, y)| join(b, [[x, y]; 2])),
+                Node::Inner(level, children) => {
+                    let half = 1 << (level - 1);
+                    let mut result = None;
+                    for (i, &child) in children.iter().enumerate() {
+                        let (dx, dy) = ((i as i64 & 1) * half, (i as i64 >> 1) * half);
+                        if let Some([min, max]) = boxes[child] {
+                            let shifted = [[min[0] + dx, min[1] + dy], [max[0] + dx, max[1] + dy]];
+                            result = join(result, shifted);
+                        }
+                    }
+                    result
+                }
+            };
+            boxes.push(bounding_box);
+        }
+        boxes[self.nodes.len()]
+    }
+
+    /// Hash of the live cells that depends neither on the engine nor on the
+    /// position of the pattern, see `hash_cells`; each node is visited once.
+    pub fn hash(&self) -> u64 {
+        let Some([min, _]) = self.bounding_box() else {
+            return hash_cells(&[]);
+        };
+        let mut sums = vec![0u64]; // of the empty node
+        for node in &self.nodes {
+            let sum = match node {
+                Node::Leaf(leaf) => leaf
+                    .iter()
+                    .map(|&(x, y)| hash_term(x, y))
+                    .fold(0, u64::wrapping_add),
+                Node::Inner(level, children) => {
+                    let half = 1 << (level - 1);
+                    let mut sum = 0u64;
+                    for (i, &child) in children.iter().enumerate() {
+                        let (dx, dy) = ((i as i64 & 1) * half, (i as i64 >> 1) * half);
+                        sum = sum.wrapping_add(sums[child].wrapping_mul(hash_term(dx, dy)));
+                    }
+                    sum
+                }
+            };
+            sums.push(sum);
+        }
+        let origin =
+            pow(inverse(HASH_X), min[0] as u64).wrapping_mul(pow(inverse(HASH_Y), min[1] as u64));
+        fmix64(sums[self.nodes.len()].wrapping_mul(origin))
+    }
+
+    /// Live cells in the coordinates of the root node.
+    pub fn cells(&self) -> Vec<(i64, i64)> {
+        self.cells_in([i64::MIN; 2], [i64::MAX; 2])
+    }
+
+    /// Live cells inside the rectangle with corners `min` and `max`, both
+    /// included; subtrees outside of it are skipped.
+    pub fn cells_in(&self, min: [i64; 2], max: [i64; 2]) -> Vec<(i64, i64)> {
+        let mut cells = vec![];
+        self.collect(self.nodes.len(), (0, 0), [min, max], &mut cells);
+        cells
+    }
+
+    fn collect(
+        &self,
+        index: usize,
+        (x0, y0): (i64, i64),
+        [min, max]: [[i64; 2]; 2],
+        cells: &mut Vec<(i64, i64)>,
+    ) {
+        if index == 0 {
+            return;
+        }
+        let inside =
+            |&(x, y): &(i64, i64)| (min[0]..=max[0]).contains(&x) && (min[1]..=max[1]).contains(&y);
+        match &self.nodes[index - 1] {
+            Node::Leaf(leaf) => {
+                let leaf = leaf.iter().map(|&(x, y)| (x0 + x, y0 + y));
+                cells.extend(leaf.filter(inside));
+            }
+            Node::Inner(level, children) => {
+                let half = 1 << (level - 1);
+                for (i, &child) in children.iter().enumerate() {
+                    let (x, y) = (x0 + (i as i64 & 1) * half, y0 + (i as i64 >> 1) * half);
+                    if x > max[0] || y > max[1] || x + half <= min[0] || y + half <= min[1] {
+                        continue;
+                    }
+                    self.collect(child, (x, y), [min, max], cells);
+                }
+            }
+        }
+    }
+
+    /// Level of the root node, as in `[M2]` files: 3 for an 8x8 leaf.
+    fn root_level(&self) -> Option<u32> {
+        self.nodes.last().map(|node| match node {
+            Node::Leaf(_) => 3,
+            Node::Inner(level, _) => *level,
+        })
+    }
+
+    /// Replaces the content of the rectangle with corners `min` and `max`,
+    /// both included, with `cells`, which must lie inside of it; everything
+    /// outside of the rectangle is cleared unless `keep_outside`.
+    ///
+    /// Cells keep the coordinates of the root node. If the rectangle does
+    /// not fit, the root grows around the old one, which shifts the old
+    /// content; the shift is returned. `level` is the level of an empty root.
+    pub fn replace(
+        &mut self,
+        mut min: [i64; 2],
+        mut max: [i64; 2],
+        mut cells: Vec<(i64, i64)>,
+        keep_outside: bool,
+        level: u32,
+    ) -> Result<[i64; 2], String> {
+        let mut root = self.nodes.len();
+        let mut level = self.root_level().unwrap_or(level).max(4);
+        let mut shift = 0;
+        while min[0] < 0 || min[1] < 0 || max[0] >= 1 << level || max[1] >= 1 << level {
+            if level == 62 {
+                return Err("the rectangle is too far from the field".to_owned());
+            }
+            root = self.grow(root, level);
+            let half = 1 << (level - 1);
+            level += 1;
+            shift += half;
+            min = min.map(|x| x + half);
+            max = max.map(|x| x + half);
+            cells
+                .iter_mut()
+                .for_each(|(x, y)| (*x, *y) = (*x + half, *y + half));
+        }
+        cells.retain(|&(x, y)| (min[0]..=max[0]).contains(&x) && (min[1]..=max[1]).contains(&y));
+        let root = self.rebuild(root, level, (0, 0), [min, max], &cells, keep_outside);
+        if root != self.nodes.len() {
+            // the root must be the last node; an empty one is an explicit node
+            let node = match root {
+                0 => Node::Inner(level, [0; 4]),
+                _ => self.nodes[root - 1].clone(),
+            };
+            self.push(node);
+        }
+        Ok([shift; 2])
+    }
+
+    fn push(&mut self, node: Node) -> usize {
+        self.nodes.push(node);
+        self.nodes.len()
+    }
+
+    /// Node of `level + 1` with the node `index` of `level` in its center.
+    fn grow(&mut self, index: usize, level: u32) -> usize {
+        if index == 0 {
+            return 0;
+        }
+        let Node::Inner(_, [nw, ne, sw, se]) = self.nodes[index - 1] else {
+            unreachable!("roots of level 4 and above are inner nodes");
+        };
+        let children = [
+            self.push(Node::Inner(level, [0, 0, 0, nw])),
+            self.push(Node::Inner(level, [0, 0, ne, 0])),
+            self.push(Node::Inner(level, [0, sw, 0, 0])),
+            self.push(Node::Inner(level, [se, 0, 0, 0])),
+        ];
+        self.push(Node::Inner(level + 1, children))
+    }
+
+    /// Node `index` of `level` at `(x0, y0)` with the rectangle replaced by
+    /// `cells`, see `replace`.
+    fn rebuild(
+        &mut self,
+        index: usize,
+        level: u32,
+        (x0, y0): (i64, i64),
+        [min, max]: [[i64; 2]; 2],
+        cells: &[(i64, i64)],
+        keep_outside: bool,
+    ) -> usize {
+        let size = 1 << level;
+        if x0 > max[0] || y0 > max[1] || x0 + size <= min[0] || y0 + size <= min[1] {
+            return if keep_outside { index } else { 0 };
+        }
+        let inside =
+            min[0] <= x0 && min[1] <= y0 && x0 + size - 1 <= max[0] && y0 + size - 1 <= max[1];
+        if cells.is_empty() && (inside || index == 0) {
+            return 0;
+        }
+        if level == 3 {
+            let mut leaf: Vec<(i64, i64)> = cells.iter().map(|&(x, y)| (x - x0, y - y0)).collect();
+            if let (false, true, Some(Node::Leaf(old))) = (
+                inside,
+                keep_outside,
+                index.checked_sub(1).map(|i| &self.nodes[i]),
+            ) {
+                let outside = |&&(x, y): &&(i64, i64)| {
+                    !((min[0]..=max[0]).contains(&(x0 + x))
+                        && (min[1]..=max[1]).contains(&(y0 + y)))
+                };
+                leaf.extend(old.iter().filter(outside));
+            }
+            return if leaf.is_empty() {
+                0
+            } else {
+                self.push(Node::Leaf(leaf))
+            };
+        }
+        let children = match (inside, index) {
+            (false, 1..) => match self.nodes[index - 1] {
+                Node::Inner(_, children) => children,
+                Node::Leaf(_) => unreachable!("nodes above level 3 are inner nodes"),
+            },
+            _ => [0; 4],
+        };
+        let half = size / 2;
+        let mut result = [0; 4];
+        for (i, child) in children.into_iter().enumerate() {
+            let (x, y) = (x0 + (i as i64 & 1) * half, y0 + (i as i64 >> 1) * half);
+            let quadrant: Vec<_> = cells
+                .iter()
+                .copied()
+                .filter(|&(cx, cy)| (x..x + half).contains(&cx) && (y..y + half).contains(&cy))
+                .collect();
+            result[i] = self.rebuild(
+                child,
+                level - 1,
+                (x, y),
+                [min, max],
+                &quadrant,
+                keep_outside,
+            );
+        }
+        if result == [0; 4] {
+            0
+        } else {
+            self.push(Node::Inner(level, result))
+        }
+    }
+
+    /// Writes the nodes reachable from the root as a MacroCell file that
+    /// `DefaultEngine::from_macrocell` loads in the same coordinates.
+    pub fn to_macrocell(&self) -> Vec<u8> {
+        let mut text = "[M2] (conway)\n#R B3/S23\n".to_owned();
+        let mut ids = vec![0; self.nodes.len() + 1];
+        let mut count = 0;
+        self.write(self.nodes.len(), &mut ids, &mut count, &mut text);
+        text.into_bytes()
+    }
+
+    fn write(&self, index: usize, ids: &mut [usize], count: &mut usize, text: &mut String) {
+        if index == 0 || ids[index] != 0 {
+            return;
+        }
+        match &self.nodes[index - 1] {
+            Node::Leaf(leaf) => {
+                let mut rows = [[b'.'; 8]; 8];
+                for &(x, y) in leaf {
+                    rows[y as usize][x as usize] = b'*';
+                }
+                let height = leaf.iter().map(|&(_, y)| y as usize + 1).max().unwrap_or(0);
+                for row in &rows[..height] {
+                    let width = row.iter().rposition(|&c| c == b'*').map_or(0, |x| x + 1);
+                    *text += std::str::from_utf8(&row[..width]).unwrap();
+                    text.push('$');
+                }
+                text.push('\n');
+            }
+            Node::Inner(level, children) => {
+                for &child in children {
+                    self.write(child, ids, count, text);
+                }
+                let [nw, ne, sw, se] = children.map(|child| ids[child]);
+                *text += &format!("{} {} {} {} {}\n", level, nw, ne, sw, se);
+            }
+        }
+        *count += 1;
+        ids[index] = *count;
+    }
+}
+
+const HASH_X: u64 = 0x9e3779b97f4a7c15;
+const HASH_Y: u64 = 0xc2b2ae3d27d4eb4f;
+
+/// Hash of a set of distinct live cells that does not change when the
+/// pattern is translated: `fmix64` of the sum of `HASH_X^(x - x0) *
+/// HASH_Y^(y - y0)` over the cells modulo 2^64, where `(x0, y0)` is the
+/// top-left corner of their bounding box.
+///
+/// Unlike a hash of the saved file, it does not depend on the order in which
+/// an engine writes its nodes, so it can be compared between engines and with
+/// cell lists produced by other programs.
+pub fn hash_cells(cells: &[(i64, i64)]) -> u64 {
+    let x0 = cells.iter().map(|c| c.0).min().unwrap_or(0);
+    let y0 = cells.iter().map(|c| c.1).min().unwrap_or(0);
+    let sum = cells
+        .iter()
+        .map(|&(x, y)| hash_term(x - x0, y - y0))
+        .fold(0, u64::wrapping_add);
+    fmix64(sum)
+}
+
+/// `HASH_X^x * HASH_Y^y` modulo 2^64, for `x, y >= 0`.
+fn hash_term(x: i64, y: i64) -> u64 {
+    pow(HASH_X, x as u64).wrapping_mul(pow(HASH_Y, y as u64))
+}
+
+fn pow(mut base: u64, mut exponent: u64) -> u64 {
+    let mut result = 1u64;
+    while exponent != 0 {
+        if exponent & 1 != 0 {
+            result = result.wrapping_mul(base);
+        }
+        base = base.wrapping_mul(base);
+        exponent >>= 1;
+    }
+    result
+}
+
+/// Multiplicative inverse of an odd number modulo 2^64, by Newton's iteration.
+fn inverse(a: u64) -> u64 {
+    let mut x = a; // correct to 3 bits, each step doubles that
+    for _ in 0..5 {
+        x = x.wrapping_mul(2u64.wrapping_sub(a.wrapping_mul(x)));
+    }
+    x
+}
+
+/// Finalizer of MurmurHash3.
+fn fmix64(mut h: u64) -> u64 {
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51afd7ed558ccd);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xc4ceb9fe1a85ec53);
+    h ^ (h >> 33)
+}
+
+/// Smallest box containing both boxes.
+fn join(a: Option<[[i64; 2]; 2]>, b: [[i64; 2]; 2]) -> Option<[[i64; 2]; 2]> {
+    let Some(a) = a else {
+        return Some(b);
+    };
+    Some([
+        [a[0][0].min(b[0][0]), a[0][1].min(b[0][1])],
+        [a[1][0].max(b[1][0]), a[1][1].max(b[1][1])],
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rle_position_round_trip() {
+        let rle = b"#CXRLE Pos=-5,7 Gen=3\nx = 3, y = 3, rule = B3/S23\nbo$2bo$3o!\n";
+        assert_eq!(header_position(rle), Some([-5, 7]));
+        let (mut engine, origin) = load_pattern_with_origin(rle, Some("rle")).unwrap();
+        let saved = save_pattern(&mut engine, PatternFormat::Rle, 3, origin, &[]).unwrap();
+        assert_eq!(saved, rle);
+    }
+
+    #[test]
+    fn detect_after_blank_lines() {
+        let rle = "\n#N Glider\n\nx = 3, y = 3, rule = B3/S23\nbo$2bo$3o!\n";
+        assert_eq!(
+            PatternFormat::detect(rle.as_bytes(), None),
+            Some(PatternFormat::Rle)
+        );
+        let empty = cells_to_plaintext(&mut vec![], &[]);
+        assert_eq!(
+            PatternFormat::detect(&empty, None),
+            Some(PatternFormat::Plaintext)
+        );
+        let glider = cells_to_plaintext(&mut vec![(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)], &[]);
+        assert_eq!(glider, b"!\n.O\n..O\nOOO\n");
+    }
+
+    #[test]
+    fn rle_position_is_not_a_rule() {
+        let rle = "#N Glider\n#R -12 -8\nx = 3, y = 3, rule = B3/S23\nbo$2bo$3o!\n";
+        assert_eq!(
+            PatternFormat::detect(rle.as_bytes(), None),
+            Some(PatternFormat::Rle)
+        );
+        assert_eq!(parse_rle(rle).map(|cells| cells.len()), Ok(5));
+
+        let highlife = "#R -12 -8\nx = 3, y = 3, rule = B36/S23\nbo$2bo$3o!\n";
+        let err = parse_rle(highlife).unwrap_err();
+        assert_eq!((err.line, err.column), (2, 22));
+        assert_eq!(
+            err.kind,
+            ParseErrorKind::UnsupportedRule("B36/S23".to_owned())
+        );
+    }
+
+    #[test]
+    fn parse_error_positions() {
+        let err = parse_rle("x = 3, y = 3\nbo$2bq$3o!\n").unwrap_err();
+        assert_eq!(
+            err,
+            ParseError::at(1, 5, ParseErrorKind::UnexpectedCharacter('q'))
+        );
+        assert_eq!(
+            err.to_string(),
+            "line 2, column 6: unexpected character 'q'"
+        );
+
+        let err = parse_plaintext("!Name: glider\n.O\n..x\n").unwrap_err();
+        assert_eq!(
+            err,
+            ParseError::at(2, 2, ParseErrorKind::UnexpectedCharacter('x'))
+        );
+
+        let err = MacroCellTree::parse(b"[M2]\n.*$\n5 1 0 0 0\n")
+            .err()
+            .unwrap();
+        assert_eq!(err, ParseError::at(2, 0, ParseErrorKind::MalformedNode));
+    }
+
+    #[test]
+    fn macrocell_measures() {
+        // the same glider leaf in the north-west and south-east quadrants
+        let tree = MacroCellTree::parse(b"[M2]\n.*$..*$***$\n4 1 0 0 1\n").unwrap();
+        assert_eq!(tree.population(), 10);
+        assert_eq!(tree.bounding_box(), Some([[0, 0], [10, 10]]));
+        assert_eq!(tree.cells_in([8, 8], [15, 15]).len(), 5);
+
+        let glider = tree.cells_in([0, 0], [7, 7]);
+        let shifted = glider
+            .iter()
+            .map(|&(x, y)| (x - 5, y + 9))
+            .collect::<Vec<_>>();
+        assert_eq!(hash_cells(&glider), hash_cells(&shifted));
+        assert_eq!(tree.hash(), hash_cells(&tree.cells()));
+        assert_ne!(tree.hash(), hash_cells(&glider));
+    }
+
+    #[test]
+    fn macrocell_edits() {
+        let mut tree = MacroCellTree::parse(b"[M2]\n.*$..*$***$\n4 1 0 0 1\n").unwrap();
+        // clear the south-east glider, then paste a block across the border
+        assert_eq!(tree.replace([8, 8], [15, 15], vec![], true, 4), Ok([0; 2]));
+        let block = vec![(15, 0), (16, 0), (15, 1), (16, 1)];
+        assert_eq!(tree.replace([15, 0], [16, 1], block, true, 4), Ok([8; 2]));
+
+        let tree = MacroCellTree::parse(&tree.to_macrocell()).unwrap();
+        let mut cells = tree.cells();
+        cells.sort_unstable();
+        let mut expected = vec![(9, 8), (10, 9), (8, 10), (9, 10), (10, 10)];
+        expected.extend([(23, 8), (24, 8), (23, 9), (24, 9)]);
+        expected.sort_unstable();
+        assert_eq!(cells, expected);
+        assert_eq!(tree.root_level(), Some(5));
+    }
+
+    #[test]
+    fn macrocell_rule() {
+        let life = b"[M2] (golly 4.2)\n#R B3/S23\n#G 10\n.*$\n";
+        assert!(MacroCellTree::parse(life).is_ok());
+
+        let highlife = b"[M2] (golly 4.2)\n#R B36/S23\n.*$\n";
+        let err = MacroCellTree::parse(highlife).err().unwrap();
+        assert_eq!(
+            err.kind,
+            ParseErrorKind::UnsupportedRule("B36/S23".to_owned())
+        );
+    }
+}
@@ -0,0 +1,118 @@
+use gol_engines::Topology;
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+/// Saved state of the field and the viewport attached to it.
+pub struct Snapshot {
+    pub label: String, // Action that leads from this state to the next one.
+    pub macrocell: Arc<Vec<u8>>,
+    pub generation: u64,
+    pub origin: [i64; 2], // Cell at the `(0, 0)` of the saved file.
+    pub topology: Topology,
+    pub viewport_pos_x: f64,
+    pub viewport_pos_y: f64,
+    pub viewport_size: f64,
+}
+
+/// Undo/redo stacks of field snapshots.
+///
+/// Every snapshot is a full MacroCell copy of the field that is loaded again
+/// on undo; sharing the engine's immutable nodes between snapshots needs
+/// access to them in `gol_engines`.
+pub struct History {
+    undo: VecDeque<Snapshot>,
+    redo: Vec<Snapshot>,
+    pub depth: usize,        // Maximum number of undo entries.
+    pub memory_limit: usize, // Maximum total size of the snapshots in bytes.
+}
+
+impl History {
+    pub fn new(depth: usize, memory_limit: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: vec![],
+            depth,
+            memory_limit,
+        }
+    }
+
+    /// Undo entries, the most recent one last.
+    pub fn undo_entries(&self) -> impl DoubleEndedIterator<Item = &Snapshot> {
+        self.undo.iter()
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// The entry `steps` steps back.
+    pub fn undo_target(&self, steps: usize) -> Option<&Snapshot> {
+        self.undo.len().checked_sub(steps).map(|i| &self.undo[i])
+    }
+
+    /// The entry one step forward.
+    pub fn redo_target(&self) -> Option<&Snapshot> {
+        self.redo.last()
+    }
+
+    /// Total size of the snapshots in bytes.
+    pub fn memory(&self) -> usize {
+        self.undo
+            .iter()
+            .chain(&self.redo)
+            .map(|snapshot| snapshot.macrocell.len())
+            .sum()
+    }
+
+    /// Records the state before a new action; the redo stack is discarded.
+    pub fn push(&mut self, snapshot: Snapshot) {
+        self.undo.push_back(snapshot);
+        self.redo.clear();
+        self.truncate();
+    }
+
+    /// Goes `steps` entries back from `current` and returns the state to restore.
+    pub fn undo(&mut self, steps: usize, mut current: Snapshot) -> Snapshot {
+        for _ in 0..steps {
+            let Some(prev) = self.undo.pop_back() else {
+                break;
+            };
+            current.label.clone_from(&prev.label);
+            self.redo.push(current);
+            current = prev;
+        }
+        self.truncate();
+        current
+    }
+
+    /// Goes one entry forward from `current` and returns the state to restore.
+    pub fn redo(&mut self, mut current: Snapshot) -> Snapshot {
+        let Some(next) = self.redo.pop() else {
+            return current;
+        };
+        current.label.clone_from(&next.label);
+        self.undo.push_back(current);
+        self.truncate();
+        next
+    }
+
+    /// Drops the oldest undo entries beyond `depth`, then more of them and
+    /// the farthest redo entries until the snapshots fit into `memory_limit`.
+    pub fn truncate(&mut self) {
+        while self.undo.len() > self.depth {
+            self.undo.pop_front();
+        }
+        let mut memory = self.memory();
+        while memory > self.memory_limit {
+            let dropped = match self.undo.pop_front() {
+                Some(snapshot) => snapshot,
+                None => self.redo.remove(0),
+            };
+            memory -= dropped.macrocell.len();
+        }
+    }
+}
@@ -0,0 +1,31 @@
+//! Pattern loading and simulation helpers shared by the viewer and the
+//! command-line tools.
+
+mod advance;
+pub mod bench;
+pub mod formats;
+mod measure;
+
+pub use advance::advance;
+pub use measure::{hash, measure, population, Measure};
+
+/// Pattern on top of the recursive OTCA metapixel hierarchy: a glider.
+pub const OTCA_TOP_PATTERN: [[u8; 8]; 8] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 0, 1, 0, 0, 0, 0, 0],
+    [1, 1, 1, 0, 0, 0, 0, 0],
+    [0; 8],
+    [0; 8],
+    [0; 8],
+    [0; 8],
+    [0; 8],
+];
+
+/// Message of a caught panic, as passed to `panic!`.
+pub fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_owned())
+}
@@ -4,12 +4,18 @@ mod config;
 mod draw;
 mod field_source;
 mod fps_limit;
+mod history;
+mod selection;
+mod worker;
 
 pub use app::App;
 use brightness::BrightnessStrategy;
 pub use config::Config;
+use conway::formats;
 use field_source::FieldSource;
 use fps_limit::FpsLimiter;
+use history::{History, Snapshot};
+use worker::{Edit, Job, Loader, Worker, WorkerOutput};
 
 fn main() {
     use eframe::egui::{vec2, ViewportBuilder};
@@ -0,0 +1,34 @@
+use super::formats::MacroCellTree;
+use gol_engines::GoLEngine;
+
+/// Exact population and bounding box of a field.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct Measure {
+    pub population: u128,
+    pub bounding_box: Option<[[i64; 2]; 2]>, // Top-left and bottom-right live cells.
+}
+
+/// Measures the field from its MacroCell representation, counting every
+/// shared node once.
+///
+/// Every call saves and parses the whole field: a population cached per
+/// node needs `GoLEngine::population` in `gol_engines`, which is deferred.
+pub fn measure(engine: &mut dyn GoLEngine) -> Result<Measure, String> {
+    let tree = MacroCellTree::parse(&engine.save_as_macrocell())?;
+    Ok(Measure {
+        population: tree.population(),
+        bounding_box: tree.bounding_box(),
+    })
+}
+
+/// Exact number of alive cells.
+pub fn population(engine: &mut dyn GoLEngine) -> u128 {
+    let tree = MacroCellTree::parse(&engine.save_as_macrocell());
+    tree.expect("Engine wrote malformed MacroCell").population()
+}
+
+/// Translation-invariant hash of the alive cells, see `formats::hash_cells`.
+pub fn hash(engine: &mut dyn GoLEngine) -> u64 {
+    let tree = MacroCellTree::parse(&engine.save_as_macrocell());
+    tree.expect("Engine wrote malformed MacroCell").hash()
+}
@@ -0,0 +1,183 @@
+use super::{app::Pending, formats, App, Config, Edit, Job};
+use gol_engines::GoLEngine;
+
+impl App {
+    /// Copies the selected cells to the clipboard as RLE.
+    pub fn copy_selection(&mut self) {
+        let (Some([min, max]), None) = (self.selection_bounds(), &self.pending) else {
+            return;
+        };
+        let encoder = move |engine: &mut dyn GoLEngine| formats::region_as_rle(engine, min, max);
+        self.spawn(Job::Encode(Box::new(encoder)), Pending::Copy);
+    }
+
+    /// Copies the selected cells to the clipboard and clears them.
+    pub fn cut_selection(&mut self) {
+        self.edit_selection("Cut", true, true, |_| vec![]);
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.edit_selection("Clear", true, false, |_| vec![]);
+    }
+
+    pub fn clear_outside_selection(&mut self) {
+        self.edit_selection("Clear outside", false, false, |cells| cells);
+    }
+
+    /// Replaces the selected cells with a random soup of `random_fill_density`.
+    pub fn fill_selection_randomly(&mut self) {
+        let Some([min, max]) = self.selection_bounds() else {
+            return;
+        };
+        let density = self.random_fill_density;
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(1, |time| time.as_nanos() as u64);
+        self.edit_selection("Random fill", true, false, move |_| {
+            let [width, height] = [max[0] - min[0] + 1, max[1] - min[1] + 1];
+            let cells = conway::bench::random_rect(width, height, density, seed);
+            cells
+                .into_iter()
+                .map(|(x, y)| (min[0] + x, min[1] + y))
+                .collect()
+        });
+    }
+
+    /// Whether the selection is small enough for `fill_selection_randomly`.
+    pub fn can_fill_selection(&self) -> bool {
+        self.selection_bounds().is_some_and(|[min, max]| {
+            let [width, height] = [max[0] - min[0] + 1, max[1] - min[1] + 1];
+            width as i128 * height as i128 <= Config::MAX_RANDOM_FILL_AREA
+        })
+    }
+
+    /// Rotates the selection clockwise around its top-left corner.
+    pub fn rotate_selection(&mut self) {
+        let Some([min, max]) = self.selection_bounds() else {
+            return;
+        };
+        let rotated = [min, [min[0] + max[1] - min[1], min[1] + max[0] - min[0]]];
+        let region = [min, [max[0].max(rotated[1][0]), max[1].max(rotated[1][1])]];
+        let edit = Edit {
+            min: region[0],
+            max: region[1],
+            keep_outside: true,
+            copy: false,
+            cells: Box::new(move |cells| {
+                cells
+                    .into_iter()
+                    .filter(|&(x, y)| x <= max[0] && y <= max[1])
+                    .map(|(x, y)| (min[0] + max[1] - y, min[1] + x - min[0]))
+                    .collect()
+            }),
+        };
+        self.edit("Rotate", edit, Some(rotated));
+    }
+
+    /// Mirrors the selection left to right, or top to bottom.
+    pub fn flip_selection(&mut self, horizontally: bool) {
+        let Some([min, max]) = self.selection_bounds() else {
+            return;
+        };
+        let label = if horizontally {
+            "Flip horizontally"
+        } else {
+            "Flip vertically"
+        };
+        self.edit_selection(label, true, false, move |cells| {
+            let flip = |(x, y)| match horizontally {
+                true => (min[0] + max[0] - x, y),
+                false => (x, min[1] + max[1] - y),
+            };
+            cells.into_iter().map(flip).collect()
+        });
+    }
+
+    /// Starts previewing the RLE pattern `text` at the cursor until it is
+    /// placed with `place_paste`.
+    pub fn start_paste(&mut self, text: &str) {
+        match formats::parse_rle(text) {
+            Ok(cells) if cells.is_empty() => {}
+            Ok(mut cells) => {
+                let x0 = cells.iter().map(|c| c.0).min().unwrap();
+                let y0 = cells.iter().map(|c| c.1).min().unwrap();
+                cells
+                    .iter_mut()
+                    .for_each(|(x, y)| (*x, *y) = (*x - x0, *y - y0));
+                self.paste = Some(cells);
+            }
+            Err(err) => self.error = Some(format!("Failed to paste: {}", err)),
+        }
+    }
+
+    /// Size of the pattern being pasted.
+    pub fn paste_size(&self) -> Option<[i64; 2]> {
+        let cells = self.paste.as_ref()?;
+        let width = cells.iter().map(|c| c.0).max()? + 1;
+        let height = cells.iter().map(|c| c.1).max()? + 1;
+        Some([width, height])
+    }
+
+    /// Adds the pattern being pasted to the field with its top-left corner at
+    /// `cell`, and selects it.
+    pub fn place_paste(&mut self, cell: [i64; 2]) {
+        let (Some([width, height]), None) = (self.paste_size(), &self.pending) else {
+            return;
+        };
+        let pasted: Vec<_> = self
+            .paste
+            .take()
+            .unwrap()
+            .into_iter()
+            .map(|(x, y)| (cell[0] + x, cell[1] + y))
+            .collect();
+        let region = [cell, [cell[0] + width - 1, cell[1] + height - 1]];
+        let edit = Edit {
+            min: region[0],
+            max: region[1],
+            keep_outside: true,
+            copy: false,
+            cells: Box::new(move |mut cells| {
+                cells.extend(pasted);
+                cells.sort_unstable();
+                cells.dedup();
+                cells
+            }),
+        };
+        self.edit("Paste", edit, Some(region));
+    }
+
+    /// Edits the selected rectangle, which stays selected.
+    fn edit_selection(
+        &mut self,
+        label: &str,
+        keep_outside: bool,
+        copy: bool,
+        cells: impl FnOnce(Vec<(i64, i64)>) -> Vec<(i64, i64)> + Send + 'static,
+    ) {
+        let Some([min, max]) = self.selection_bounds() else {
+            return;
+        };
+        let edit = Edit {
+            min,
+            max,
+            keep_outside,
+            copy,
+            cells: Box::new(cells),
+        };
+        self.edit(label, edit, Some([min, max]));
+    }
+
+    /// Starts `edit` in the background, recording the field before it in the
+    /// history; `selection` is selected afterwards.
+    fn edit(&mut self, label: &str, edit: Edit, selection: Option<[[i64; 2]; 2]>) {
+        if self.pending.is_some() {
+            return;
+        }
+        let pending = Pending::Edit {
+            record: self.snapshot(label),
+            selection,
+        };
+        self.spawn(Job::Edit(edit), pending);
+    }
+}
@@ -0,0 +1,263 @@
+use super::Config;
+use conway::formats::{self, MacroCellTree};
+use conway::Measure;
+use gol_engines::{DefaultEngine, GoLEngine, Topology};
+use std::panic::AssertUnwindSafe;
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    mpsc::{self, Receiver, Sender, TryRecvError},
+    Arc,
+};
+
+/// Builds a new field and the cell at the `(0, 0)` of its source, failing
+/// with a message for the error dialog.
+pub type Loader = Box<dyn FnOnce() -> Result<(Box<dyn GoLEngine + Send>, [i64; 2]), String> + Send>;
+
+/// Encodes the field, e.g. to save it to a file.
+pub type Encoder = Box<dyn FnOnce(&mut dyn GoLEngine) -> Result<Vec<u8>, String> + Send>;
+
+/// Maps the live cells inside of the edited rectangle to their replacement.
+pub type Cells = Box<dyn FnOnce(Vec<(i64, i64)>) -> Vec<(i64, i64)> + Send>;
+
+/// Change of the live cells inside a rectangle of the field.
+pub struct Edit {
+    pub min: [i64; 2], // Top-left and bottom-right cells of the rectangle.
+    pub max: [i64; 2],
+    pub keep_outside: bool, // Whether the cells outside of the rectangle are kept.
+    pub copy: bool,         // Whether the cells inside are returned as RLE.
+    pub cells: Cells,       // The replacement must lie inside of the rectangle as well.
+}
+
+/// Work on the whole field that is too slow for the GUI thread.
+pub enum Job {
+    /// Advances the field by exactly `generations` generations, in chunks of
+    /// at most `2^chunk_log2` generations to begin with.
+    Advance {
+        generations: u64,
+        topology: Topology,
+        chunk_log2: u32,
+    },
+    /// Replaces the field with the one built by the loader.
+    Replace(Loader),
+    /// Edits the field through its MacroCell representation.
+    Edit(Edit),
+    /// Encodes the field without changing it.
+    Encode(Encoder),
+    /// Measures the population and the bounding box of the field.
+    Measure,
+}
+
+/// Result of a finished or cancelled background job.
+pub struct WorkerOutput {
+    pub engine: Box<dyn GoLEngine + Send>, // The old field if the loader failed.
+    pub saved: Option<Vec<u8>>,            // MacroCell of the field before the job, if requested.
+    pub result: Result<(), String>,        // Failure of the loader, edit or encoder.
+    pub data: Vec<u8>,                     // Output of the encoder or the copied cells.
+    pub generations: u64,                  // Number of generations actually computed.
+    pub offset: [i64; 2],                  // Total shift of the field during the job.
+    pub origin: [i64; 2],                  // Origin returned by the loader.
+    pub duration: f64,                     // Duration of the job in seconds.
+    pub chunk_log2: u32,                   // Chunk size the next update should start with.
+    pub measure: Option<Measure>,          // Population and bounding box, if measured.
+}
+
+/// Field and job sent to the worker thread.
+struct Request {
+    engine: Box<dyn GoLEngine + Send>,
+    job: Job,
+    save: bool,
+}
+
+/// Runs jobs on the field on a long-lived background thread, so the GUI
+/// stays responsive.
+///
+/// The engine is owned by the thread until the job finishes and is handed
+/// back by `join` or `try_join`.
+pub struct Worker {
+    requests: Sender<Request>,
+    outputs: Receiver<Result<WorkerOutput, String>>,
+    generations: Option<u64>, // `None` unless advancing.
+    progress: Arc<AtomicU64>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Worker {
+    pub fn new() -> Self {
+        let (requests, receiver) = mpsc::channel::<Request>();
+        let (sender, outputs) = mpsc::channel();
+        let progress = Arc::new(AtomicU64::new(0));
+        let cancelled = Arc::new(AtomicBool::new(false));
+        {
+            let (progress, cancelled) = (progress.clone(), cancelled.clone());
+            // exits once the `Worker` and with it `requests` are dropped
+            std::thread::spawn(move || {
+                for Request { engine, job, save } in receiver {
+                    let run = || Self::run(engine, job, save, &progress, &cancelled);
+                    let output = std::panic::catch_unwind(AssertUnwindSafe(run))
+                        .map_err(|payload| conway::panic_message(&*payload));
+                    if sender.send(output).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        Self {
+            requests,
+            outputs,
+            generations: None,
+            progress,
+            cancelled,
+        }
+    }
+
+    /// Starts `job`; if `save` is set, the field is saved as MacroCell before.
+    /// The previous job must be joined first.
+    pub fn start(&mut self, engine: Box<dyn GoLEngine + Send>, job: Job, save: bool) {
+        self.generations = match job {
+            Job::Advance { generations, .. } => Some(generations),
+            _ => None,
+        };
+        self.progress.store(0, Ordering::Relaxed);
+        self.cancelled.store(false, Ordering::Relaxed);
+        let request = Request { engine, job, save };
+        self.requests.send(request).expect("Worker thread stopped");
+    }
+
+    fn run(
+        mut engine: Box<dyn GoLEngine + Send>,
+        job: Job,
+        save: bool,
+        progress: &AtomicU64,
+        cancelled: &AtomicBool,
+    ) -> WorkerOutput {
+        let timer = std::time::Instant::now();
+        let saved = save.then(|| engine.save_as_macrocell());
+        let mut output = WorkerOutput {
+            engine,
+            saved,
+            result: Ok(()),
+            data: vec![],
+            generations: 0,
+            offset: [0; 2],
+            origin: [0; 2],
+            duration: 0.,
+            chunk_log2: 0,
+            measure: None,
+        };
+        match job {
+            Job::Advance {
+                generations,
+                topology,
+                chunk_log2,
+            } => {
+                Self::advance(
+                    &mut output,
+                    generations,
+                    topology,
+                    chunk_log2,
+                    progress,
+                    cancelled,
+                );
+            }
+            Job::Replace(loader) => match loader() {
+                Ok((engine, origin)) => {
+                    output.engine = engine;
+                    output.origin = origin;
+                }
+                Err(message) => output.result = Err(message),
+            },
+            Job::Edit(edit) => output.result = Self::edit(&mut output, edit),
+            Job::Encode(encoder) => match encoder(&mut *output.engine) {
+                Ok(data) => output.data = data,
+                Err(message) => output.result = Err(message),
+            },
+            Job::Measure => match conway::measure(&mut *output.engine) {
+                Ok(measure) => output.measure = Some(measure),
+                Err(message) => {
+                    output.result = Err(format!("Failed to measure the field: {}", message))
+                }
+            },
+        }
+        output.duration = timer.elapsed().as_secs_f64();
+        output
+    }
+
+    /// A single engine update cannot be interrupted, so the advance is split
+    /// into chunks whose size is adjusted to take about `Config::UPDATE_BUDGET`
+    /// each; progress is reported between them and cancellation is checked
+    /// between engine updates.
+    fn advance(
+        output: &mut WorkerOutput,
+        generations: u64,
+        topology: Topology,
+        mut chunk_log2: u32,
+        progress: &AtomicU64,
+        cancelled: &AtomicBool,
+    ) {
+        while output.generations < generations && !cancelled.load(Ordering::Relaxed) {
+            let chunk = (generations - output.generations).min(1 << chunk_log2);
+            let timer = std::time::Instant::now();
+            let proceed = |_| !cancelled.load(Ordering::Relaxed);
+            let (n, [dx, dy]) = conway::advance(&mut *output.engine, chunk, topology, proceed);
+            let duration = timer.elapsed().as_secs_f64();
+
+            output.generations += n;
+            output.offset[0] += dx;
+            output.offset[1] += dy;
+            progress.store(output.generations, Ordering::Relaxed);
+            if duration > Config::UPDATE_BUDGET {
+                chunk_log2 = chunk_log2.saturating_sub(1);
+            } else if duration < Config::UPDATE_BUDGET / 2. && chunk == 1 << chunk_log2 {
+                chunk_log2 = (chunk_log2 + 1).min(63);
+            }
+        }
+        output.chunk_log2 = chunk_log2;
+    }
+
+    /// Loads the edited MacroCell representation of the field, which keeps
+    /// the coordinates of its root node.
+    fn edit(output: &mut WorkerOutput, edit: Edit) -> Result<(), String> {
+        let engine = &mut output.engine;
+        let mut tree = MacroCellTree::parse(&engine.save_as_macrocell())?;
+        let inside = tree.cells_in(edit.min, edit.max);
+        if edit.copy {
+            output.data = formats::cells_to_rle(&mut inside.clone());
+        }
+        let cells = (edit.cells)(inside);
+        let level = engine.side_length_log2();
+        output.offset = tree.replace(edit.min, edit.max, cells, edit.keep_outside, level)?;
+        *engine = Box::new(DefaultEngine::from_macrocell(&tree.to_macrocell()));
+        Ok(())
+    }
+
+    /// Fraction of the requested generations that is already computed;
+    /// `None` if the job is not an advance.
+    pub fn progress(&self) -> Option<f32> {
+        let generations = self.generations?;
+        Some((self.progress.load(Ordering::Relaxed) as f64 / generations as f64) as f32)
+    }
+
+    /// Asks the thread to stop advancing after the engine update in progress.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Waits for the job and takes back the engine; fails with the panic
+    /// message if the engine panicked, in which case the field is lost.
+    pub fn join(&self) -> Result<WorkerOutput, String> {
+        self.outputs.recv().expect("Worker thread stopped")
+    }
+
+    /// Like `join`, but returns `None` if the job is still running.
+    pub fn try_join(&self) -> Option<Result<WorkerOutput, String>> {
+        match self.outputs.try_recv() {
+            Ok(output) => Some(output),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => panic!("Worker thread stopped"),
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use conway::{bench, formats};
+use gol_engines::{DefaultEngine, GoLEngine, Topology};
+use std::collections::HashSet;
+
+type Cells = HashSet<(i64, i64)>;
+type Loader = fn(&[u8]) -> Box<dyn GoLEngine>;
+
+/// Engines under test; each of them is compared with the naive reference and
+/// with the first one.
+const ENGINES: &[(&str, Loader)] = &[("default", |rle| Box::new(DefaultEngine::from_rle(rle)))];
+
+const SIDE: i64 = 32;
+const FILL_RATE: f64 = 0.4;
+const SEEDS: [u64; 4] = [1, 2, 3, 42];
+const GENERATIONS: [u64; 3] = [1, 37, 100];
+
+/// Live cells of a MacroCell file, in coordinates of its root node.
+fn macrocell_cells(data: &[u8]) -> Cells {
+    let tree = formats::MacroCellTree::parse(data).unwrap();
+    tree.cells().into_iter().collect()
+}
+
+/// Result of an engine run, comparable between engines: the cells are
+/// relative to the top-left corner of the initial field's bounding box, and
+/// reduced modulo the side on the torus.
+#[derive(Debug, PartialEq)]
+struct Outcome {
+    wrap: Option<i64>, // Side of the torus.
+    cells: Cells,
+    population: u128,
+    hash: u64,
+}
+
+/// Runs the engine and checks it against the naive simulation in its own
+/// coordinates.
+fn run_engine(
+    name: &str,
+    loader: Loader,
+    seed: u64,
+    generations: u64,
+    topology: Topology,
+) -> Outcome {
+    let mut engine = loader(&bench::random_soup(SIDE, FILL_RATE, seed));
+    let initial = macrocell_cells(&engine.save_as_macrocell());
+    let wrap = match topology {
+        Topology::Unbounded => None,
+        Topology::Torus => Some(1 << engine.side_length_log2()),
+    };
+    let (done, offset) = conway::advance(&mut *engine, generations, topology, |_| true);
+    assert_eq!(done, generations);
+    let reduce = |x: i64, side: Option<i64>| side.map_or(x, |side| x.rem_euclid(side));
+    let cells: Cells = macrocell_cells(&engine.save_as_macrocell())
+        .into_iter()
+        .map(|(x, y)| (reduce(x - offset[0], wrap), reduce(y - offset[1], wrap)))
+        .collect();
+    let population = conway::population(&mut *engine);
+    let hash = conway::hash(&mut *engine);
+
+    let mut naive = initial.clone();
+    for _ in 0..generations {
+        naive = bench::naive_step(&naive, wrap);
+    }
+    assert!(
+        cells == naive,
+        "{} differs from the naive simulation: seed={} generations={} {:?}",
+        name,
+        seed,
+        generations,
+        topology
+    );
+    assert_eq!(population, naive.len() as u128);
+    let naive = naive.into_iter().collect::<Vec<_>>();
+    assert_eq!(hash, formats::hash_cells(&naive));
+
+    let x0 = initial.iter().map(|c| c.0).min().unwrap_or(0);
+    let y0 = initial.iter().map(|c| c.1).min().unwrap_or(0);
+    let cells = cells
+        .into_iter()
+        .map(|(x, y)| (reduce(x - x0, wrap), reduce(y - y0, wrap)))
+        .collect();
+    Outcome {
+        wrap,
+        cells,
+        population,
+        hash,
+    }
+}
+
+fn check(topology: Topology) {
+    for seed in SEEDS {
+        for generations in GENERATIONS {
+            let outcomes = ENGINES
+                .iter()
+                .map(|&(name, loader)| {
+                    let outcome = run_engine(name, loader, seed, generations, topology);
+                    (name, outcome)
+                })
+                .collect::<Vec<_>>();
+
+            let (reference_name, reference) = &outcomes[0];
+            // tori of different sides evolve differently once the field wraps
+            let comparable = outcomes[1..]
+                .iter()
+                .filter(|(_, outcome)| outcome.wrap == reference.wrap);
+            for (name, outcome) in comparable {
+                assert!(
+                    outcome == reference,
+                    "{} differs from {}: seed={} generations={} {:?}",
+                    name,
+                    reference_name,
+                    seed,
+                    generations,
+                    topology
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_consistency_unbounded() {
+    check(Topology::Unbounded);
+}
+
+#[test]
+fn test_consistency_torus() {
+    check(Topology::Torus);
+}